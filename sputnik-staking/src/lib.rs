@@ -5,7 +5,7 @@ use near_sdk::collections::LookupMap;
 use near_sdk::json_types::{ValidAccountId, WrappedDuration, U128};
 use near_sdk::{
     env, log, ext_contract, near_bindgen, AccountId, Balance, BorshStorageKey, Duration, Gas,
-    PanicOnDefault, Promise, PromiseOrValue, PromiseResult,
+    PanicOnDefault, Promise, PromiseOrValue, PromiseResult, StorageUsage,
 };
 
 pub use user::{User, VersionedUser};
@@ -31,6 +31,10 @@ pub const GAS_FOR_REGISTER: Gas = 10_000_000_000_000;
 /// Amount of gas for undelegate action.
 pub const GAS_FOR_UNDELEGATE: Gas = 10_000_000_000_000;
 
+/// Storage of the delegation history in the DAO, paid on each delegate and undelegate action.
+/// Must match `DELEGATION_STORAGE` of the DAO.
+pub const DELEGATION_STORAGE: StorageUsage = 1_000;
+
 /// Amount of gas for undelegate action.
 pub const TMP_GAS: Gas = 10_000_000_000_000;
 
@@ -127,6 +131,7 @@ impl Contract {
 
     /// Delegate give amount of votes to given account.
     /// If enough tokens and storage, forwards this to owner account.
+    /// Storage of the delegation history in the owner is paid from the sender's storage deposit.
     pub fn delegate(&mut self, account_id: ValidAccountId, amount: U128) -> Promise {
         let sender_id = env::predecessor_account_id();
         self.internal_delegate(sender_id.clone(), account_id.clone().into(), amount.0);
@@ -134,7 +139,7 @@ impl Contract {
             account_id.into(),
             amount,
             &self.owner_id,
-            0,
+            DELEGATION_STORAGE as Balance * env::storage_byte_cost(),
             GAS_FOR_DELEGATE,
         )
         .then(ext_self::on_stake_change(
@@ -146,6 +151,7 @@ impl Contract {
     }
 
     /// Remove given amount of delegation.
+    /// Storage of the delegation history in the owner is paid from the sender's storage deposit.
     pub fn undelegate(&mut self, account_id: ValidAccountId, amount: U128) -> Promise {
         let sender_id = env::predecessor_account_id();
         self.internal_undelegate(sender_id.clone(), account_id.clone().into(), amount.0);
@@ -153,7 +159,7 @@ impl Contract {
             account_id.into(),
             amount,
             &self.owner_id,
-            0,
+            DELEGATION_STORAGE as Balance * env::storage_byte_cost(),
             GAS_FOR_UNDELEGATE,
         )
         .then(ext_self::on_stake_change(
@@ -379,5 +385,26 @@ mod tests {
         let user = contract.get_user(accounts(2));
         assert_eq!(user.delegated_amount(), 0);
         assert_eq!(user.next_action_timestamp, U64(period));
+        // Storage of the delegation history in the DAO is paid from the storage deposit.
+        assert_eq!(
+            user.near_amount.0,
+            to_yocto("1") - 2 * DELEGATION_STORAGE as Balance * env::storage_byte_cost()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ENOUGH_STORAGE")]
+    fn test_delegate_without_storage() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let mut contract = Contract::new(accounts(0), accounts(1), U64(1000));
+        testing_env!(context
+            .attached_deposit(User::min_storage() as Balance * env::storage_byte_cost())
+            .build());
+        contract.storage_deposit(Some(accounts(2)), None);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_on_transfer(accounts(2), U128(to_yocto("100")), "".to_string());
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.delegate(accounts(3), U128(to_yocto("10")));
     }
 }
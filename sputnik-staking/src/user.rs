@@ -105,6 +105,14 @@ impl User {
         self.next_action_timestamp = (env::block_timestamp() + undelegation_period).into();
     }
 
+    /// Pays storage of the delegation history in the DAO from the storage deposit.
+    /// Fails if the rest doesn't cover storage used here.
+    pub fn pay_delegation_storage(&mut self) {
+        let cost = DELEGATION_STORAGE as Balance * env::storage_byte_cost();
+        assert!(cost <= self.storage_available(), "ERR_NOT_ENOUGH_STORAGE");
+        self.near_amount.0 -= cost;
+    }
+
     /// Withdraw the amount.
     /// Fails if there is not enough available balance.
     pub fn withdraw(&mut self, amount: Balance) {
@@ -186,6 +194,7 @@ impl Contract {
     ) {
         let mut sender = self.internal_get_user(&sender_id);
         sender.delegate(delegate_id.clone(), amount);
+        sender.pay_delegation_storage();
         self.save_user(&sender_id, sender);
     }
 
@@ -198,6 +207,7 @@ impl Contract {
     ) {
         let mut sender = self.internal_get_user(&sender_id);
         sender.undelegate(&delegate_id, amount, self.unstake_period);
+        sender.pay_delegation_storage();
         self.save_user(&sender_id, sender);
     }
 }
@@ -3,8 +3,10 @@ use std::convert::TryFrom;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
 use near_sdk::json_types::{WrappedDuration, WrappedTimestamp, U128};
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{env, near_bindgen, AccountId, Promise, PromiseOrValue};
+use near_sdk::{env, near_bindgen, AccountId, Promise, PromiseOrValue, PromiseResult};
 
+use crate::proposals::ext_self;
+use crate::types::GAS_FOR_BOUNTY_PAYOUT_CALLBACK;
 use crate::*;
 
 /// Information recorded about claim of the bounty by given user.
@@ -75,13 +77,25 @@ impl Contract {
         let (claims, claim_idx) = self.internal_get_claims(id, &receiver_id);
         self.internal_remove_claim(id, claims, claim_idx);
         if success {
-            let res = self.internal_payout(
+            // Tokens were reserved when the bounty was added, withdrawn once the payout succeeds.
+            let res = match self.internal_payout(
                 &bounty.token,
                 receiver_id,
                 bounty.amount.0,
                 format!("Bounty {} payout", id),
                 None,
-            );
+            ) {
+                PromiseOrValue::Promise(promise) => promise
+                    .then(ext_self::on_bounty_payout_callback(
+                        bounty.token.clone(),
+                        bounty.amount,
+                        &env::current_account_id(),
+                        0,
+                        GAS_FOR_BOUNTY_PAYOUT_CALLBACK,
+                    ))
+                    .into(),
+                value => value,
+            };
             if bounty.times == 0 {
                 self.bounties.remove(&id);
             } else {
@@ -210,10 +224,26 @@ impl Contract {
         self.internal_remove_claim(id, claims, claim_idx);
         result
     }
+
+    /// Records the bounty payout in the treasury. If it failed, the reserved tokens are released.
+    #[private]
+    pub fn on_bounty_payout_callback(&mut self, token_id: AccountId, amount: U128) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "ERR_UNEXPECTED_CALLBACK_PROMISES"
+        );
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => self.internal_withdraw(&token_id, amount.0),
+            PromiseResult::Failed => self.internal_release(&token_id, amount.0),
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
     use near_sdk::test_utils::{accounts, VMContextBuilder};
     use near_sdk::{testing_env, MockedBlockchain};
     use near_sdk_sim::to_yocto;
@@ -290,6 +320,53 @@ mod tests {
         assert_eq!(contract.get_bounty(0).bounty.times, 0);
     }
 
+    #[test]
+    fn test_bounty_payout_failed() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.test_track_token(&accounts(3).into());
+        contract.ft_on_transfer(accounts(2), U128(100), "".to_string());
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::AddBounty {
+                bounty: Bounty {
+                    description: "test bounty".to_string(),
+                    token: accounts(3).into(),
+                    amount: U128(40),
+                    times: 1,
+                    max_deadline: WrappedDuration::from(1_000),
+                },
+            },
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+        contract.bounty_claim(0, WrappedDuration::from(500));
+        contract.bounty_done(0, None, "Bounty is done".to_string());
+        contract.act_proposal(id + 1, Action::VoteApprove, None);
+        // Tokens stay reserved until the payout is verified.
+        assert_eq!(contract.get_treasury(0, 10)[0].1.amount, U128(100));
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(60));
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .current_account_id(accounts(0))
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.on_bounty_payout_callback(accounts(3).into(), U128(40));
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(100));
+    }
+
     #[test]
     #[should_panic(expected = "ERR_BOUNTY_ALL_CLAIMED")]
     fn test_bounty_claim_not_allowed() {
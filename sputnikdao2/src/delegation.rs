@@ -1,9 +1,372 @@
+use near_sdk::{Duration, StorageUsage};
+
+use crate::policy::WeightKind;
 use crate::*;
 
+/// Returns integer square root of given number, rounded down.
+pub fn integer_sqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+    let mut x = n / 2 + 1;
+    let mut y = (x + n / x) / 2;
+    while y < x {
+        x = y;
+        y = (x + n / x) / 2;
+    }
+    x
+}
+
+/// Returns `amount * part / total` without overflowing, given `part <= total`.
+pub(crate) fn mul_div(amount: Balance, part: Duration, total: Duration) -> Balance {
+    let (part, total) = (part as u128, total as u128);
+    amount / total * part + amount % total * part / total
+}
+
+/// Returns index of the first checkpoint after given timestamp.
+/// `get` returns checkpoint by index, checkpoints must be sorted by time.
+fn first_checkpoint_after(
+    len: u64,
+    get: &impl Fn(u64) -> (Timestamp, Balance),
+    timestamp: Timestamp,
+) -> u64 {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let mid = (low + high) / 2;
+        if get(mid).0 <= timestamp {
+            low = mid + 1;
+        } else {
+            high = mid;
+        }
+    }
+    low
+}
+
+/// Returns amount of the last checkpoint at or before given timestamp.
+fn checkpoint_at(
+    len: u64,
+    get: impl Fn(u64) -> (Timestamp, Balance),
+    timestamp: Timestamp,
+) -> Balance {
+    match first_checkpoint_after(len, &get, timestamp) {
+        0 => 0,
+        idx => get(idx - 1).1,
+    }
+}
+
+/// Returns amount averaged over time for the `period` before given timestamp.
+fn checkpoint_average(
+    len: u64,
+    get: impl Fn(u64) -> (Timestamp, Balance),
+    timestamp: Timestamp,
+    period: Duration,
+) -> Balance {
+    if period == 0 {
+        return checkpoint_at(len, get, timestamp);
+    }
+    let mut from = timestamp.saturating_sub(period);
+    let mut idx = first_checkpoint_after(len, &get, from);
+    let mut amount = if idx == 0 { 0 } else { get(idx - 1).1 };
+    let mut result = 0;
+    while idx < len && get(idx).0 <= timestamp {
+        let (time, next_amount) = get(idx);
+        result += mul_div(amount, time - from, period);
+        from = time;
+        amount = next_amount;
+        idx += 1;
+    }
+    result + mul_div(amount, timestamp - from, period)
+}
+
+/// Max number of proposals checked for the oldest one still voted on, per delegation change.
+const MAX_CHECKED_PROPOSALS: u64 = 5;
+
+/// Max storage of the history added per delegation change, in bytes.
+/// Staking contract attaches deposit for it to each `delegate` and `undelegate`.
+pub const DELEGATION_STORAGE: StorageUsage = 1_000;
+
+/// Max number of checkpoints pruned per history, per delegation change.
+/// More than one added per change, so the history catches up with the horizon.
+const MAX_PRUNED_CHECKPOINTS: u64 = 2;
+
+/// History of delegated amount kept in checkpoints.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+pub enum DelegationHistory {
+    /// Amount delegated to given account.
+    Account(AccountId),
+    /// Total delegated amount.
+    Total,
+    /// Sum of square roots of all delegations.
+    QuadraticTotal,
+}
+
+/// Returns true if votes can still be added to given proposal.
+fn can_be_voted(proposal: &Proposal) -> bool {
+    match proposal.status {
+        ProposalStatus::InProgress | ProposalStatus::Delay => true,
+        // Returns back in progress if adding it in the hub fails.
+        ProposalStatus::Moved => proposal.moved_to.is_none(),
+        _ => false,
+    }
+}
+
 impl Contract {
     pub fn get_user_weight(&self, account_id: &AccountId) -> Balance {
         self.delegations.get(account_id).unwrap_or_default()
     }
+
+    /// Returns checkpoint of given history with given index.
+    fn internal_get_checkpoint(
+        &self,
+        history: &DelegationHistory,
+        idx: u64,
+    ) -> (Timestamp, Balance) {
+        self.delegation_checkpoints
+            .get(&(history.clone(), idx))
+            .expect("ERR_NO_CHECKPOINT")
+    }
+
+    /// Returns number of kept checkpoints of given history and getter of them by index, None if it never changed.
+    fn internal_checkpoints<'a>(
+        &'a self,
+        history: &'a DelegationHistory,
+    ) -> Option<(u64, impl Fn(u64) -> (Timestamp, Balance) + 'a)> {
+        self.delegation_checkpoint_ranges
+            .get(history)
+            .map(|(first, end)| {
+                (end - first, move |i| {
+                    self.internal_get_checkpoint(history, first + i)
+                })
+            })
+    }
+
+    /// Returns amount of given history at given time. `current` is the amount if it never changed.
+    fn internal_amount_at(
+        &self,
+        history: DelegationHistory,
+        timestamp: Timestamp,
+        current: Balance,
+    ) -> Balance {
+        match self.internal_checkpoints(&history) {
+            Some((len, get)) => checkpoint_at(len, get, timestamp),
+            None => current,
+        }
+    }
+
+    /// Returns amount of given history averaged over `period` before given time.
+    /// `current` is the amount if it never changed.
+    fn internal_average_amount(
+        &self,
+        history: DelegationHistory,
+        timestamp: Timestamp,
+        period: Duration,
+        current: Balance,
+    ) -> Balance {
+        match self.internal_checkpoints(&history) {
+            Some((len, get)) => checkpoint_average(len, get, timestamp, period),
+            None => current,
+        }
+    }
+
+    /// Returns amount delegated to given account at given time.
+    pub fn get_user_weight_at(&self, account_id: &AccountId, timestamp: Timestamp) -> Balance {
+        // Delegation made before checkpoints were kept didn't change since.
+        self.internal_amount_at(
+            DelegationHistory::Account(account_id.clone()),
+            timestamp,
+            self.get_user_weight(account_id),
+        )
+    }
+
+    /// Returns total delegated amount at given time.
+    pub fn get_total_delegation_amount_at(&self, timestamp: Timestamp) -> Balance {
+        self.internal_amount_at(
+            DelegationHistory::Total,
+            timestamp,
+            self.total_delegation_amount,
+        )
+    }
+
+    /// Returns weight of the vote of given account at given time.
+    pub fn get_vote_weight_at(
+        &self,
+        account_id: &AccountId,
+        weight_kind: &WeightKind,
+        timestamp: Timestamp,
+    ) -> Balance {
+        match weight_kind {
+            WeightKind::RoleWeight => 1,
+            WeightKind::TokenWeight => self.get_user_weight_at(account_id, timestamp),
+            WeightKind::QuadraticTokenWeight => {
+                integer_sqrt(self.get_user_weight_at(account_id, timestamp))
+            }
+            WeightKind::ConvictionWeight(period) => self.internal_average_amount(
+                DelegationHistory::Account(account_id.clone()),
+                timestamp,
+                period.0,
+                self.get_user_weight(account_id),
+            ),
+            // Holding is verified with the view call to the token contract, see `act_proposal_as_holder`.
+            WeightKind::HolderWeight => env::panic(b"ERR_UNSUPPORTED_WEIGHT_KIND"),
+        }
+    }
+
+    /// Returns total weight of votes of all delegations at given time.
+    /// Not applicable to `RoleWeight` and `HolderWeight`, which depend on the role.
+    pub fn get_total_vote_weight_at(
+        &self,
+        weight_kind: &WeightKind,
+        timestamp: Timestamp,
+    ) -> Balance {
+        match weight_kind {
+            WeightKind::RoleWeight | WeightKind::HolderWeight => {
+                env::panic(b"ERR_UNSUPPORTED_WEIGHT_KIND")
+            }
+            WeightKind::TokenWeight => self.get_total_delegation_amount_at(timestamp),
+            WeightKind::QuadraticTokenWeight => {
+                // Delegations made before checkpoints were kept must be counted first.
+                // Proposals submitted before then use the first complete total.
+                assert_ne!(
+                    self.quadratic_total_since,
+                    Timestamp::MAX,
+                    "ERR_QUADRATIC_TOTAL_INCOMPLETE"
+                );
+                self.internal_amount_at(
+                    DelegationHistory::QuadraticTotal,
+                    std::cmp::max(timestamp, self.quadratic_total_since),
+                    self.total_quadratic_delegation_amount,
+                )
+            }
+            WeightKind::ConvictionWeight(period) => self.internal_average_amount(
+                DelegationHistory::Total,
+                timestamp,
+                period.0,
+                self.total_delegation_amount,
+            ),
+        }
+    }
+
+    /// Returns time before which history of delegations isn't needed anymore:
+    /// the longest conviction period before submission of the oldest proposal that can be voted on.
+    /// Skips a few proposals that can't be voted on per call, nothing is pruned until all are skipped.
+    fn internal_checkpoint_horizon(&mut self) -> Timestamp {
+        let period = self
+            .policy
+            .get()
+            .unwrap()
+            .to_policy()
+            .max_conviction_period();
+        for _ in 0..MAX_CHECKED_PROPOSALS {
+            if self.first_live_proposal_id >= self.last_proposal_id {
+                return env::block_timestamp().saturating_sub(period);
+            }
+            match self.internal_get_proposal(self.first_live_proposal_id) {
+                Some(proposal) if can_be_voted(&proposal) => {
+                    return proposal.submission_time.0.saturating_sub(period);
+                }
+                _ => self.first_live_proposal_id += 1,
+            }
+        }
+        0
+    }
+
+    /// Records amount of given history changing from `prev_amount` to `amount` at the current time.
+    /// History that never changed starts with `prev_amount`, held since before checkpoints were kept.
+    /// Prunes checkpoints replaced by later ones before `horizon`.
+    fn internal_push_checkpoint(
+        &mut self,
+        history: DelegationHistory,
+        prev_amount: Balance,
+        amount: Balance,
+        horizon: Timestamp,
+    ) {
+        let now = env::block_timestamp();
+        let (mut first, mut end) = match self.delegation_checkpoint_ranges.get(&history) {
+            Some(range) => range,
+            None if prev_amount > 0 => {
+                self.delegation_checkpoints
+                    .insert(&(history.clone(), 0), &(0, prev_amount));
+                (0, 1)
+            }
+            None => (0, 0),
+        };
+        if end > first && self.internal_get_checkpoint(&history, end - 1).0 == now {
+            end -= 1;
+        }
+        self.delegation_checkpoints
+            .insert(&(history.clone(), end), &(now, amount));
+        end += 1;
+        // Checkpoint in effect at the horizon is kept, as the amount from then on.
+        for _ in 0..MAX_PRUNED_CHECKPOINTS {
+            if first + 1 < end && self.internal_get_checkpoint(&history, first + 1).0 <= horizon {
+                self.delegation_checkpoints
+                    .remove(&(history.clone(), first));
+                first += 1;
+            } else {
+                break;
+            }
+        }
+        self.delegation_checkpoint_ranges
+            .insert(&history, &(first, end));
+    }
+
+    /// Locks storage used since `storage_usage` was measured, paid by the attached deposit.
+    /// Rest of the deposit stays with the DAO, as does storage freed by pruning.
+    fn internal_pay_storage(&mut self, storage_usage: StorageUsage) {
+        let new_storage_usage = env::storage_usage();
+        if new_storage_usage > storage_usage {
+            let cost = (new_storage_usage - storage_usage) as Balance * env::storage_byte_cost();
+            assert!(
+                cost <= env::attached_deposit(),
+                "ERR_NOT_ENOUGH_STORAGE_DEPOSIT"
+            );
+            self.locked_amount += cost;
+        } else {
+            self.locked_amount = self.locked_amount.saturating_sub(
+                (storage_usage - new_storage_usage) as Balance * env::storage_byte_cost(),
+            );
+        }
+    }
+
+    /// Records delegated amount of given account changing from `prev_amount` to `amount` in the history.
+    fn internal_checkpoint(
+        &mut self,
+        account_id: &AccountId,
+        prev_amount: Balance,
+        amount: Balance,
+    ) {
+        let horizon = self.internal_checkpoint_horizon();
+        let history = DelegationHistory::Account(account_id.clone());
+        // Delegation made before checkpoints were kept isn't counted in the quadratic total yet.
+        let prev_sqrt = if self.delegation_checkpoint_ranges.contains_key(&history) {
+            integer_sqrt(prev_amount)
+        } else {
+            if prev_amount > 0 {
+                self.uncounted_delegation_amount =
+                    self.uncounted_delegation_amount.saturating_sub(prev_amount);
+                if self.uncounted_delegation_amount == 0 {
+                    self.quadratic_total_since = env::block_timestamp();
+                }
+            }
+            0
+        };
+        self.internal_push_checkpoint(history, prev_amount, amount, horizon);
+        self.internal_push_checkpoint(
+            DelegationHistory::Total,
+            self.total_delegation_amount + prev_amount - amount,
+            self.total_delegation_amount,
+            horizon,
+        );
+        let prev_quadratic_amount = self.total_quadratic_delegation_amount;
+        self.total_quadratic_delegation_amount =
+            prev_quadratic_amount.saturating_sub(prev_sqrt) + integer_sqrt(amount);
+        self.internal_push_checkpoint(
+            DelegationHistory::QuadraticTotal,
+            prev_quadratic_amount,
+            self.total_quadratic_delegation_amount,
+            horizon,
+        );
+    }
 }
 
 #[near_bindgen]
@@ -20,7 +383,33 @@ impl Contract {
         self.delegations.insert(account_id, &0);
     }
 
-    pub fn delegate(&mut self, account_id: &AccountId, amount: U128) -> (Balance, Balance, Balance) {
+    /// Counts delegations to given accounts made before checkpoints were kept in the quadratic total.
+    /// Quadratic total is complete once all of them are counted. Can be called by anyone,
+    /// with deposit for the storage of the history, see `DELEGATION_STORAGE`.
+    #[payable]
+    pub fn count_delegations(&mut self, account_ids: Vec<AccountId>) {
+        let storage_usage = env::storage_usage();
+        for account_id in account_ids {
+            let amount = self.get_user_weight(&account_id);
+            if amount > 0
+                && !self
+                    .delegation_checkpoint_ranges
+                    .contains_key(&DelegationHistory::Account(account_id.clone()))
+            {
+                self.internal_checkpoint(&account_id, amount, amount);
+            }
+        }
+        self.internal_pay_storage(storage_usage);
+    }
+
+    /// Adds given amount to delegation of given account. Called by the staking contract,
+    /// with deposit for the storage of the history taken from the delegator's storage deposit there.
+    #[payable]
+    pub fn delegate(
+        &mut self,
+        account_id: &AccountId,
+        amount: U128,
+    ) -> (Balance, Balance, Balance) {
         let staking_id = self.staking_id.clone().expect("ERR_NO_STAKING");
         assert_eq!(
             env::predecessor_account_id(),
@@ -32,13 +421,22 @@ impl Contract {
             .get(account_id)
             .expect("ERR_NOT_REGISTERED");
         let new_amount = prev_amount + amount.0;
-        self.delegations
-            .insert(account_id, &new_amount);
+        let storage_usage = env::storage_usage();
+        self.delegations.insert(account_id, &new_amount);
         self.total_delegation_amount += amount.0;
+        self.internal_checkpoint(account_id, prev_amount, new_amount);
+        self.internal_pay_storage(storage_usage);
         return (prev_amount, new_amount, self.total_delegation_amount);
     }
 
-    pub fn undelegate(&mut self, account_id: &AccountId, amount: U128) -> (Balance, Balance, Balance) {
+    /// Removes given amount from delegation of given account. Called by the staking contract,
+    /// with deposit for the storage of the history as in `delegate`.
+    #[payable]
+    pub fn undelegate(
+        &mut self,
+        account_id: &AccountId,
+        amount: U128,
+    ) -> (Balance, Balance, Balance) {
         let staking_id = self.staking_id.clone().expect("ERR_NO_STAKING");
         assert_eq!(
             env::predecessor_account_id(),
@@ -48,9 +446,194 @@ impl Contract {
         let prev_amount = self.delegations.get(account_id).unwrap_or_default();
         let new_amount = prev_amount - amount.0;
         assert!(prev_amount >= amount.0, "ERR_INVALID_STAKING_CONTRACT");
-        self.delegations
-            .insert(account_id, &new_amount);
+        let storage_usage = env::storage_usage();
+        self.delegations.insert(account_id, &new_amount);
         self.total_delegation_amount -= amount.0;
+        self.internal_checkpoint(account_id, prev_amount, new_amount);
+        self.internal_pay_storage(storage_usage);
         return (prev_amount, new_amount, self.total_delegation_amount);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::WrappedDuration;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+    use near_sdk_sim::to_yocto;
+
+    use super::*;
+
+    #[test]
+    fn test_delegation_checkpoints() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.staking_id = Some(accounts(0).into());
+        // History since the submission of the proposal in progress is kept.
+        testing_env!(context.attached_deposit(to_yocto("1")).build());
+        contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Vote,
+        });
+        let user: AccountId = accounts(2).into();
+        testing_env!(context
+            .attached_deposit(16 * env::storage_byte_cost())
+            .build());
+        contract.register_delegation(&user);
+        testing_env!(context
+            .attached_deposit(DELEGATION_STORAGE as Balance * env::storage_byte_cost())
+            .block_timestamp(100)
+            .build());
+        contract.delegate(&user, U128(10));
+        testing_env!(context.block_timestamp(200).build());
+        contract.delegate(&user, U128(5));
+        contract.undelegate(&user, U128(3));
+        assert_eq!(contract.get_user_weight_at(&user, 50), 0);
+        assert_eq!(contract.get_user_weight_at(&user, 100), 10);
+        assert_eq!(contract.get_user_weight_at(&user, 150), 10);
+        assert_eq!(contract.get_user_weight_at(&user, 300), 12);
+        assert_eq!(contract.get_total_delegation_amount_at(150), 10);
+        assert_eq!(contract.get_total_delegation_amount_at(200), 12);
+        assert_eq!(
+            contract.get_vote_weight_at(&user, &WeightKind::QuadraticTokenWeight, 300),
+            3
+        );
+        assert_eq!(
+            contract.get_total_vote_weight_at(&WeightKind::QuadraticTokenWeight, 150),
+            3
+        );
+        // Held 10 for half of the period and 12 for the other half.
+        let conviction = WeightKind::ConvictionWeight(WrappedDuration::from(200));
+        assert_eq!(contract.get_vote_weight_at(&user, &conviction, 300), 11);
+        assert_eq!(contract.get_total_vote_weight_at(&conviction, 300), 11);
+        // Not held long enough yet.
+        assert_eq!(contract.get_vote_weight_at(&user, &conviction, 200), 5);
+        assert_eq!(contract.get_vote_weight_at(&user, &conviction, 50), 0);
+    }
+
+    #[test]
+    fn test_delegation_checkpoints_pruned() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.staking_id = Some(accounts(0).into());
+        let user: AccountId = accounts(2).into();
+        testing_env!(context
+            .attached_deposit(16 * env::storage_byte_cost())
+            .build());
+        contract.register_delegation(&user);
+        let locked_amount = contract.locked_amount;
+        testing_env!(context
+            .attached_deposit(DELEGATION_STORAGE as Balance * env::storage_byte_cost())
+            .block_timestamp(100)
+            .build());
+        contract.delegate(&user, U128(10));
+        // Storage of the checkpoints is paid by the deposit and locked.
+        assert!(contract.locked_amount > locked_amount);
+        testing_env!(context.block_timestamp(200).build());
+        contract.delegate(&user, U128(5));
+        let locked_amount = contract.locked_amount;
+        // No proposals to vote on, so only the current amount is kept.
+        testing_env!(context.block_timestamp(300).build());
+        contract.delegate(&user, U128(1));
+        assert_eq!(
+            contract
+                .delegation_checkpoint_ranges
+                .get(&DelegationHistory::Account(user.clone())),
+            Some((2, 3))
+        );
+        assert_eq!(contract.locked_amount, locked_amount);
+        assert_eq!(contract.get_user_weight_at(&user, 300), 16);
+        assert_eq!(contract.get_total_delegation_amount_at(300), 16);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ENOUGH_STORAGE_DEPOSIT")]
+    fn test_delegation_without_storage_deposit() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.staking_id = Some(accounts(0).into());
+        let user: AccountId = accounts(2).into();
+        testing_env!(context
+            .attached_deposit(16 * env::storage_byte_cost())
+            .build());
+        contract.register_delegation(&user);
+        testing_env!(context.attached_deposit(0).block_timestamp(100).build());
+        contract.delegate(&user, U128(10));
+    }
+
+    #[test]
+    fn test_delegation_before_checkpoints() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(0)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.staking_id = Some(accounts(0).into());
+        testing_env!(context.attached_deposit(to_yocto("1")).build());
+        contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Vote,
+        });
+        // Delegated before the upgrade, without checkpoints.
+        let user: AccountId = accounts(2).into();
+        contract.delegations.insert(&user, &16);
+        contract.total_delegation_amount = 16;
+        assert_eq!(contract.get_user_weight_at(&user, 50), 16);
+        testing_env!(context.block_timestamp(100).build());
+        contract.undelegate(&user, U128(7));
+        // Amount before the change is backfilled into the history.
+        assert_eq!(contract.get_user_weight_at(&user, 50), 16);
+        assert_eq!(contract.get_total_delegation_amount_at(50), 16);
+        assert_eq!(contract.get_user_weight_at(&user, 100), 9);
+        assert_eq!(
+            contract.get_total_vote_weight_at(&WeightKind::QuadraticTokenWeight, 100),
+            3
+        );
+    }
+
+    #[test]
+    fn test_integer_sqrt() {
+        assert_eq!(integer_sqrt(0), 0);
+        assert_eq!(integer_sqrt(1), 1);
+        assert_eq!(integer_sqrt(2), 1);
+        assert_eq!(integer_sqrt(3), 1);
+        assert_eq!(integer_sqrt(4), 2);
+        assert_eq!(integer_sqrt(15), 3);
+        assert_eq!(integer_sqrt(16), 4);
+        assert_eq!(integer_sqrt(10u128.pow(24)), 10u128.pow(12));
+        assert_eq!(integer_sqrt(u128::MAX), u64::MAX as u128);
+    }
+
+    #[test]
+    fn test_checkpoint_average() {
+        let checkpoints = vec![(100, 10), (200, 20)];
+        let get = |i: u64| checkpoints[i as usize];
+        // No history before the window.
+        assert_eq!(checkpoint_average(2, get, 100, 100), 0);
+        // Zero period is the same as the amount at the time.
+        assert_eq!(checkpoint_average(2, get, 150, 0), 10);
+        assert_eq!(checkpoint_average(2, get, 150, 100), 5);
+        assert_eq!(checkpoint_average(2, get, 300, 200), 15);
+        assert_eq!(checkpoint_average(2, get, 1_000, 100), 20);
+        assert_eq!(checkpoint_average(0, get, 1_000, 100), 0);
+        // Large balances don't overflow.
+        let checkpoints = vec![(0, u128::MAX / 2)];
+        assert_eq!(
+            checkpoint_average(1, |i| checkpoints[i as usize], u64::MAX, u64::MAX),
+            u128::MAX / 2
+        );
+    }
+}
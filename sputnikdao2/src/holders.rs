@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+
+use near_sdk::json_types::U128;
+use near_sdk::serde_json;
+use near_sdk::{
+    env, ext_contract, log, near_bindgen, AccountId, Balance, Gas, Promise, PromiseResult,
+};
+
+use crate::policy::UserInfo;
+use crate::proposals::ext_self;
+use crate::types::{
+    BASE_TOKEN, GAS_FOR_HOLDER_PROPOSAL_REFUND, GAS_FOR_HOLDING_CHECK, GAS_FOR_HOLDING_VIEW,
+};
+use crate::*;
+
+/// Views of the token contracts used to check holding for token gated roles.
+#[ext_contract(ext_token_holding)]
+pub trait TokenHolding {
+    /// NEP-141 balance of given account.
+    fn ft_balance_of(&self, account_id: AccountId) -> U128;
+    /// NEP-171 number of tokens owned by given account (`nft_supply_for_owner` of its enumeration extension).
+    fn nft_supply_for_owner(&self, account_id: AccountId) -> U128;
+}
+
+impl Contract {
+    /// Calls the token contract of given token gated role for holding of given account.
+    /// Returns the view call and minimum holding required by the role.
+    fn internal_holding_view(&self, role: &String, account_id: &AccountId) -> (Promise, U128) {
+        let policy = self.policy.get().unwrap().to_policy();
+        match policy
+            .roles
+            .iter()
+            .find(|role_info| &role_info.name == role)
+            .map(|role_info| &role_info.kind)
+        {
+            Some(RoleKind::TokenHolder {
+                token_id,
+                min_balance,
+            }) => (
+                ext_token_holding::ft_balance_of(
+                    account_id.clone(),
+                    token_id,
+                    0,
+                    GAS_FOR_HOLDING_VIEW,
+                ),
+                *min_balance,
+            ),
+            Some(RoleKind::NftHolder {
+                contract_id,
+                min_tokens,
+            }) => (
+                ext_token_holding::nft_supply_for_owner(
+                    account_id.clone(),
+                    contract_id,
+                    0,
+                    GAS_FOR_HOLDING_VIEW,
+                ),
+                *min_tokens,
+            ),
+            _ => env::panic(b"ERR_NOT_TOKEN_GATED_ROLE"),
+        }
+    }
+
+    /// Gas left for the callback after scheduling the view call and given gas for the calls after it.
+    fn internal_holding_callback_gas(reserved: Gas) -> Gas {
+        let reserved = GAS_FOR_HOLDING_VIEW + GAS_FOR_HOLDING_CHECK + reserved;
+        let gas = env::prepaid_gas().saturating_sub(env::used_gas());
+        assert!(gas > reserved, "ERR_NOT_ENOUGH_GAS");
+        gas - reserved
+    }
+
+    /// Returns given role with the holding returned by the view call, if it's at least given minimum.
+    /// Failed view or unexpected result count as no holding.
+    fn internal_verified_holder_roles(role: String, min_holding: U128) -> HashMap<String, Balance> {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "ERR_UNEXPECTED_CALLBACK_PROMISES"
+        );
+        let holding = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => serde_json::from_slice::<U128>(&value)
+                .map(|holding| holding.0)
+                .unwrap_or_default(),
+            PromiseResult::Failed => 0,
+        };
+        let mut holder_roles = HashMap::default();
+        if holding >= min_holding.0 {
+            holder_roles.insert(role, holding);
+        }
+        holder_roles
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Add proposal to this DAO as member of given token gated role.
+    /// Holding is checked with the view call to the token contract, bond is refunded if proposal isn't added.
+    #[payable]
+    pub fn add_proposal_as_holder(&mut self, proposal: ProposalInput, role: String) -> Promise {
+        let policy = self.policy.get().unwrap().to_policy();
+        assert_eq!(
+            policy.proposal_bond_token, BASE_TOKEN,
+            "ERR_WRONG_BOND_TOKEN"
+        );
+        assert!(
+            env::attached_deposit() >= policy.get_proposal_bond(&proposal.kind),
+            "ERR_MIN_BOND"
+        );
+        self.internal_validate_proposal_kind(&proposal.kind);
+        // Refund callback is scheduled after it.
+        let callback_gas = Self::internal_holding_callback_gas(
+            GAS_FOR_HOLDING_CHECK + GAS_FOR_HOLDER_PROPOSAL_REFUND,
+        );
+        let proposer = env::predecessor_account_id();
+        let (view, min_holding) = self.internal_holding_view(&role, &proposer);
+        view.then(ext_self::on_add_proposal_as_holder(
+            proposer.clone(),
+            proposal,
+            role,
+            min_holding,
+            U128(env::attached_deposit()),
+            &env::current_account_id(),
+            0,
+            callback_gas,
+        ))
+        .then(ext_self::on_proposal_added_as_holder(
+            proposer,
+            U128(env::attached_deposit()),
+            &env::current_account_id(),
+            0,
+            GAS_FOR_HOLDER_PROPOSAL_REFUND,
+        ))
+    }
+
+    /// Act on given proposal as member of given token gated role.
+    /// Holding is checked with the view call to the token contract.
+    pub fn act_proposal_as_holder(
+        &mut self,
+        id: u64,
+        action: Action,
+        memo: Option<String>,
+        role: String,
+    ) -> Promise {
+        let callback_gas = Self::internal_holding_callback_gas(0);
+        let sender_id = env::predecessor_account_id();
+        let (view, min_holding) = self.internal_holding_view(&role, &sender_id);
+        view.then(ext_self::on_act_proposal_as_holder(
+            sender_id,
+            id,
+            action,
+            memo,
+            role,
+            min_holding,
+            &env::current_account_id(),
+            0,
+            callback_gas,
+        ))
+    }
+
+    /// Adds proposal if the proposer holds enough tokens for given role or is allowed by other roles.
+    /// Otherwise refunds the bond and returns None.
+    #[private]
+    pub fn on_add_proposal_as_holder(
+        &mut self,
+        proposer: AccountId,
+        proposal: ProposalInput,
+        role: String,
+        min_holding: U128,
+        deposit: U128,
+    ) -> Option<u64> {
+        let holder_roles = Self::internal_verified_holder_roles(role, min_holding);
+        // Policy could have changed since the proposal was submitted.
+        let policy = self.policy.get().unwrap().to_policy();
+        let user = UserInfo {
+            holder_roles: holder_roles.clone(),
+            ..self.internal_user_info(&policy, &proposer)
+        };
+        if policy.proposal_bond_token != BASE_TOKEN
+            || deposit.0 < policy.get_proposal_bond(&proposal.kind)
+            || !policy.can_add_proposal(&user, &proposal.kind)
+        {
+            log!("ERR_PERMISSION_DENIED");
+            Promise::new(proposer).transfer(deposit.0);
+            return None;
+        }
+        Some(self.internal_add_proposal(proposer, proposal, &policy, holder_roles))
+    }
+
+    /// Refunds the bond if adding the proposal failed, e.g. it's not valid anymore after the view call.
+    /// Returns id of the added proposal.
+    #[private]
+    pub fn on_proposal_added_as_holder(
+        &mut self,
+        proposer: AccountId,
+        deposit: U128,
+    ) -> Option<u64> {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "ERR_UNEXPECTED_CALLBACK_PROMISES"
+        );
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => serde_json::from_slice(&value).unwrap_or_default(),
+            PromiseResult::Failed => {
+                log!("ERR_PROPOSAL_NOT_ADDED");
+                Promise::new(proposer).transfer(deposit.0);
+                None
+            }
+        }
+    }
+
+    /// Acts on given proposal with roles of the sender, including given role if they hold enough tokens.
+    #[private]
+    pub fn on_act_proposal_as_holder(
+        &mut self,
+        sender_id: AccountId,
+        id: u64,
+        action: Action,
+        memo: Option<String>,
+        role: String,
+        min_holding: U128,
+    ) {
+        let holder_roles = Self::internal_verified_holder_roles(role, min_holding);
+        self.internal_act_proposal(sender_id, holder_roles, id, action, memo);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+    use near_sdk_sim::to_yocto;
+
+    use crate::delegation::DELEGATION_STORAGE;
+    use crate::policy::{VotePolicy, WeightKind, WeightOrRatio};
+    use crate::proposals::ProposalStatus;
+
+    use super::*;
+
+    /// Sets up callback context with given result of the view call.
+    fn holding_result(context: &mut VMContextBuilder, holding: Option<u128>) {
+        let result = match holding {
+            Some(holding) => PromiseResult::Successful(serde_json::to_vec(&U128(holding)).unwrap()),
+            None => PromiseResult::Failed,
+        };
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .current_account_id(accounts(0))
+                .attached_deposit(0)
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![result]
+        );
+    }
+
+    /// Sets up contract where holders of NFT vote with given vote policies.
+    fn holders_contract(vote_policy: HashMap<String, VotePolicy>) -> Contract {
+        let mut policy = VersionedPolicy::Default(vec![accounts(1).into()]).upgrade();
+        // Only council and holders can add proposals.
+        policy
+            .to_policy_mut()
+            .roles
+            .retain(|role| role.name != "all");
+        // Holders vote with delegated tokens.
+        policy.to_policy_mut().default_vote_policy = VotePolicy {
+            weight_kind: WeightKind::TokenWeight,
+            threshold: WeightOrRatio::Weight(U128(4)),
+            ..VotePolicy::default()
+        };
+        policy.to_policy_mut().roles.push(RolePermission {
+            name: "holders".to_string(),
+            kind: RoleKind::NftHolder {
+                contract_id: accounts(4).into(),
+                min_tokens: U128(1),
+            },
+            permissions: vec!["vote:*".to_string()].into_iter().collect(),
+            scoped_permissions: vec![],
+            vote_policy,
+            allowances: HashMap::default(),
+            parent: None,
+        });
+        let mut contract = Contract::new(Config::test_config(), policy);
+        contract.staking_id = Some(accounts(0).into());
+        contract
+    }
+
+    /// Delegates given amount to given account.
+    fn delegate(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        user: usize,
+        amount: u128,
+    ) {
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .attached_deposit(16 * env::storage_byte_cost())
+            .build());
+        let user: AccountId = accounts(user).into();
+        contract.register_delegation(&user);
+        testing_env!(context
+            .attached_deposit(DELEGATION_STORAGE as u128 * env::storage_byte_cost())
+            .build());
+        contract.delegate(&user, U128(amount));
+    }
+
+    #[test]
+    fn test_nft_holders() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let mut contract = holders_contract(HashMap::default());
+        delegate(&mut context, &mut contract, 2, 3);
+        delegate(&mut context, &mut contract, 3, 1);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .attached_deposit(to_yocto("1"))
+            .block_timestamp(1)
+            .build());
+        contract.add_proposal_as_holder(
+            ProposalInput {
+                description: "test".to_string(),
+                kind: ProposalKind::Vote,
+            },
+            "holders".to_string(),
+        );
+        holding_result(&mut context, Some(1));
+        let id = contract
+            .on_add_proposal_as_holder(
+                accounts(2).into(),
+                ProposalInput {
+                    description: "test".to_string(),
+                    kind: ProposalKind::Vote,
+                },
+                "holders".to_string(),
+                U128(1),
+                U128(to_yocto("1")),
+            )
+            .unwrap();
+
+        holding_result(&mut context, Some(3));
+        contract.on_act_proposal_as_holder(
+            accounts(2).into(),
+            id,
+            Action::VoteApprove,
+            None,
+            "holders".to_string(),
+            U128(1),
+        );
+        assert_eq!(
+            contract.get_proposal(id).proposal.vote_counts["holders"],
+            [3, 0, 0]
+        );
+        // Token moved to another account doesn't add weight to the vote.
+        holding_result(&mut context, Some(1));
+        contract.on_act_proposal_as_holder(
+            accounts(5).into(),
+            id,
+            Action::VoteApprove,
+            None,
+            "holders".to_string(),
+            U128(1),
+        );
+        assert_eq!(
+            contract.get_proposal(id).proposal.vote_counts["holders"],
+            [3, 0, 0]
+        );
+        holding_result(&mut context, Some(1));
+        contract.on_act_proposal_as_holder(
+            accounts(3).into(),
+            id,
+            Action::VoteApprove,
+            None,
+            "holders".to_string(),
+            U128(1),
+        );
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    fn test_holder_weight() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let mut contract = holders_contract(
+            vec![(
+                "vote".to_string(),
+                VotePolicy {
+                    weight_kind: WeightKind::HolderWeight,
+                    quorum: U128(5),
+                    ..VotePolicy::default()
+                },
+            )]
+            .into_iter()
+            .collect(),
+        );
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Vote,
+        });
+        holding_result(&mut context, Some(3));
+        contract.on_act_proposal_as_holder(
+            accounts(2).into(),
+            id,
+            Action::VoteApprove,
+            None,
+            "holders".to_string(),
+            U128(1),
+        );
+        assert_eq!(
+            contract.get_proposal(id).proposal.vote_counts["holders"],
+            [3, 0, 0]
+        );
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::InProgress
+        );
+        holding_result(&mut context, Some(2));
+        contract.on_act_proposal_as_holder(
+            accounts(3).into(),
+            id,
+            Action::VoteApprove,
+            None,
+            "holders".to_string(),
+            U128(1),
+        );
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    fn test_failed_holder_proposal_refunded() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let mut contract = holders_contract(HashMap::default());
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .current_account_id(accounts(0))
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        assert_eq!(
+            contract.on_proposal_added_as_holder(accounts(2).into(), U128(to_yocto("1"))),
+            None
+        );
+        testing_env!(
+            context.build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(
+                serde_json::to_vec(&Some(0u64)).unwrap()
+            )]
+        );
+        assert_eq!(
+            contract.on_proposal_added_as_holder(accounts(2).into(), U128(to_yocto("1"))),
+            Some(0)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ENOUGH_GAS")]
+    fn test_act_as_holder_not_enough_gas() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let mut contract = holders_contract(HashMap::default());
+        testing_env!(context
+            .prepaid_gas(GAS_FOR_HOLDING_VIEW + GAS_FOR_HOLDING_CHECK)
+            .build());
+        contract.act_proposal_as_holder(0, Action::VoteApprove, None, "holders".to_string());
+    }
+
+    #[test]
+    fn test_not_holder_bond_refunded() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let mut contract = holders_contract(HashMap::default());
+        holding_result(&mut context, Some(0));
+        assert_eq!(
+            contract.on_add_proposal_as_holder(
+                accounts(2).into(),
+                ProposalInput {
+                    description: "test".to_string(),
+                    kind: ProposalKind::Vote,
+                },
+                "holders".to_string(),
+                U128(1),
+                U128(to_yocto("1")),
+            ),
+            None
+        );
+        assert_eq!(contract.get_last_proposal_id(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PERMISSION_DENIED")]
+    fn test_not_holder_vote() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let mut contract = holders_contract(HashMap::default());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Vote,
+        });
+        holding_result(&mut context, None);
+        contract.on_act_proposal_as_holder(
+            accounts(2).into(),
+            id,
+            Action::VoteApprove,
+            None,
+            "holders".to_string(),
+            U128(1),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_TOKEN_GATED_ROLE")]
+    fn test_act_as_holder_wrong_role() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = holders_contract(HashMap::default());
+        contract.act_proposal_as_holder(0, Action::VoteApprove, None, "council".to_string());
+    }
+}
@@ -0,0 +1,276 @@
+use std::collections::HashSet;
+
+use near_sdk::json_types::ValidAccountId;
+use near_sdk::{near_bindgen, AccountId};
+
+use crate::*;
+
+impl Contract {
+    /// Returns id under which members of given large group role are stored.
+    fn internal_large_group_id(&self, role: &String) -> Option<u64> {
+        self.large_group_ids.get(role)
+    }
+
+    /// Returns number of members of given large group role.
+    fn internal_large_group_size(&self, role: &String) -> u64 {
+        self.internal_large_group_id(role)
+            .and_then(|id| self.large_group_sizes.get(&id))
+            .unwrap_or_default()
+    }
+
+    /// Sets sizes of the large groups in given policy to the number of stored members.
+    pub(crate) fn internal_sync_large_groups(&self, policy: &mut Policy) {
+        policy.set_large_group_sizes(|role| self.internal_large_group_size(role));
+    }
+
+    /// Returns current policy with sizes of the large groups.
+    pub(crate) fn internal_policy(&self) -> Policy {
+        let mut policy = self.policy.get().unwrap().to_policy();
+        self.internal_sync_large_groups(&mut policy);
+        policy
+    }
+
+    /// Forgets members of the large groups of `old_policy` that aren't large groups in `new_policy`,
+    /// so a large group added later with the same name starts empty.
+    pub(crate) fn internal_remove_large_groups(
+        &mut self,
+        old_policy: &Policy,
+        new_policy: &Policy,
+    ) {
+        for role in old_policy.roles.iter() {
+            if matches!(role.kind, RoleKind::LargeGroup) && !new_policy.is_large_group(&role.name) {
+                self.large_group_ids.remove(&role.name);
+            }
+        }
+    }
+
+    /// Adds member to given large group role.
+    pub(crate) fn internal_add_large_group_member(&mut self, role: &String, member_id: &AccountId) {
+        let id = match self.internal_large_group_id(role) {
+            Some(id) => id,
+            None => {
+                self.last_large_group_id += 1;
+                self.large_group_ids.insert(role, &self.last_large_group_id);
+                self.last_large_group_id
+            }
+        };
+        if self.large_group_members.insert(&(id, member_id.clone())) {
+            self.large_group_sizes.insert(
+                &id,
+                &(self.large_group_sizes.get(&id).unwrap_or_default() + 1),
+            );
+        }
+    }
+
+    /// Removes member from given large group role.
+    pub(crate) fn internal_remove_large_group_member(
+        &mut self,
+        role: &String,
+        member_id: &AccountId,
+    ) {
+        if let Some(id) = self.internal_large_group_id(role) {
+            if self.large_group_members.remove(&(id, member_id.clone())) {
+                self.large_group_sizes.insert(
+                    &id,
+                    &self
+                        .large_group_sizes
+                        .get(&id)
+                        .unwrap_or_default()
+                        .saturating_sub(1),
+                );
+            }
+        }
+    }
+
+    /// Returns names of large group roles in given policy that given account is member of.
+    pub(crate) fn internal_large_groups_of(
+        &self,
+        policy: &Policy,
+        account_id: &AccountId,
+    ) -> HashSet<String> {
+        policy
+            .roles
+            .iter()
+            .filter(|role| matches!(role.kind, RoleKind::LargeGroup))
+            .filter(|role| self.internal_is_large_group_member(&role.name, account_id))
+            .map(|role| role.name.clone())
+            .collect()
+    }
+
+    /// Returns true if given account is stored as member of given large group role.
+    fn internal_is_large_group_member(&self, role: &String, account_id: &AccountId) -> bool {
+        self.internal_large_group_id(role).map_or(false, |id| {
+            self.large_group_members.contains(&(id, account_id.clone()))
+        })
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns true if given account is member of given large group role.
+    pub fn is_large_group_member(&self, role: String, account_id: ValidAccountId) -> bool {
+        self.internal_is_large_group_member(&role, account_id.as_ref())
+    }
+
+    /// Returns number of members of given large group role.
+    pub fn get_large_group_size(&self, role: String) -> u64 {
+        self.internal_large_group_size(&role)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use near_sdk::json_types::WrappedDuration;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+    use near_sdk_sim::to_yocto;
+
+    use crate::proposals::ProposalStatus;
+
+    use super::*;
+
+    fn add_member(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        member_id: ValidAccountId,
+    ) {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::AddMemberToRole {
+                member_id,
+                role: "community".to_string(),
+                term: None,
+            },
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+    }
+
+    fn change_policy(context: &mut VMContextBuilder, contract: &mut Contract, policy: Policy) {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::ChangePolicy {
+                policy: VersionedPolicy::Current(policy),
+            },
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+    }
+
+    fn large_group_policy() -> Policy {
+        let mut policy = VersionedPolicy::Default(vec![accounts(1).into()])
+            .upgrade()
+            .to_policy();
+        policy.roles.push(RolePermission {
+            name: "community".to_string(),
+            kind: RoleKind::LargeGroup,
+            permissions: vec!["vote:*".to_string()].into_iter().collect(),
+            scoped_permissions: vec![],
+            vote_policy: HashMap::default(),
+            allowances: HashMap::default(),
+            parent: None,
+        });
+        policy
+    }
+
+    #[test]
+    fn test_large_group() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Current(large_group_policy()),
+        );
+        assert_eq!(contract.get_large_group_size("community".to_string()), 0);
+        add_member(&mut context, &mut contract, accounts(2));
+        add_member(&mut context, &mut contract, accounts(3));
+        add_member(&mut context, &mut contract, accounts(3));
+        assert_eq!(contract.get_large_group_size("community".to_string()), 2);
+        assert!(contract.is_large_group_member("community".to_string(), accounts(2)));
+
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Vote,
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.vote_counts["community"],
+            [1, 0, 0]
+        );
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.act_proposal(id, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::RemoveMemberFromRole {
+                member_id: accounts(2),
+                role: "community".to_string(),
+            },
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+        assert_eq!(contract.get_large_group_size("community".to_string()), 1);
+        assert!(!contract.is_large_group_member("community".to_string(), accounts(2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_LARGE_GROUP_TERM")]
+    fn test_large_group_term() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Current(large_group_policy()),
+        );
+        contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::AddMemberToRole {
+                member_id: accounts(2),
+                role: "community".to_string(),
+                term: Some(WrappedDuration::from(1_000)),
+            },
+        });
+    }
+
+    #[test]
+    fn test_large_group_removed() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Current(large_group_policy()),
+        );
+        add_member(&mut context, &mut contract, accounts(2));
+        assert_eq!(contract.get_large_group_size("community".to_string()), 1);
+
+        // Group is kept with its members while it stays in the policy.
+        change_policy(&mut context, &mut contract, large_group_policy());
+        assert_eq!(contract.get_large_group_size("community".to_string()), 1);
+
+        let mut policy = large_group_policy();
+        policy.roles.pop();
+        change_policy(&mut context, &mut contract, policy);
+        change_policy(&mut context, &mut contract, large_group_policy());
+        assert_eq!(contract.get_large_group_size("community".to_string()), 0);
+        assert!(!contract.is_large_group_member("community".to_string(), accounts(2)));
+    }
+}
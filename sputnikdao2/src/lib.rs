@@ -1,23 +1,41 @@
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::{LazyOption, LookupMap};
+use near_sdk::collections::{LazyOption, LookupMap, LookupSet, Vector};
 #[cfg(target_arch = "wasm32")]
 use near_sdk::env::BLOCKCHAIN_INTERFACE;
 use near_sdk::json_types::{Base58CryptoHash, ValidAccountId, U128};
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{
     env, near_bindgen, AccountId, Balance, BorshStorageKey, CryptoHash, PanicOnDefault, Promise,
+    Timestamp,
 };
 
 use crate::bounties::{Bounty, BountyClaim, VersionedBounty};
-pub use crate::policy::{Policy, RoleKind, RolePermission, VersionedPolicy, VotePolicy};
+use crate::delegation::DelegationHistory;
+pub use crate::delegation::DELEGATION_STORAGE;
+pub use crate::permissions::{PermissionCondition, ScopedPermission};
+pub use crate::policy::{
+    AmountThreshold, OptimisticApproval, Policy, PolicyError, RoleKind, RolePermission,
+    SpendingAllowance, VersionedPolicy, VotePolicy, WeightKind,
+};
+pub use crate::polls::{PollOption, PollTally};
 use crate::proposals::VersionedProposal;
-pub use crate::proposals::{Proposal, ProposalInput, ProposalKind, ProposalStatus};
+pub use crate::proposals::{Proposal, ProposalInput, ProposalKind, ProposalStatus, Vote};
+use crate::recurring::{RecurringTransfer, VersionedRecurringTransfer};
+use crate::streams::{Stream, VersionedStream};
+pub use crate::treasury::TokenBalance;
 pub use crate::types::{Action, Config};
 
 mod bounties;
 mod delegation;
+mod holders;
+mod large_groups;
+mod permissions;
 mod policy;
+mod polls;
 mod proposals;
+mod recurring;
+mod streams;
+mod treasury;
 mod types;
 pub mod views;
 
@@ -36,6 +54,16 @@ pub enum StorageKeys {
     BountyClaimers,
     BountyClaimCounts,
     Blobs,
+    DelegationCheckpoints,
+    DelegationCheckpointRanges,
+    Treasury,
+    TreasuryTokens,
+    Streams,
+    RecurringTransfers,
+    Spendings,
+    LargeGroupMembers,
+    LargeGroupSizes,
+    LargeGroupIds,
 }
 
 #[near_bindgen]
@@ -46,8 +74,17 @@ pub struct Contract {
     /// Voting and permissions policy.
     pub policy: LazyOption<VersionedPolicy>,
 
-    /// Amount of $NEAR locked for storage / bonds.
+    /// Amount of $NEAR locked for storage / bonds / payouts of approved proposals.
     pub locked_amount: Balance,
+    /// $NEAR bonds of proposals from this id are in `locked_amount` until returned or kept.
+    /// Proposals before it were added before bonds were locked.
+    pub first_locked_bond_id: u64,
+    /// Balances of fungible tokens held by this DAO per token id.
+    pub treasury: LookupMap<AccountId, TokenBalance>,
+    /// Ids of tokens in the treasury.
+    pub treasury_tokens: Vector<AccountId>,
+    /// Amounts spent via allowance per (role, token) as (timestamp, amount), within the allowance window.
+    pub spendings: LookupMap<(String, AccountId), Vec<(Timestamp, Balance)>>,
 
     /// Vote staking contract id. That contract must have this account as owner.
     pub staking_id: Option<AccountId>,
@@ -55,6 +92,18 @@ pub struct Contract {
     pub total_delegation_amount: Balance,
     /// Delegations per user.
     pub delegations: LookupMap<AccountId, Balance>,
+    /// Histories of delegations per user and of the totals as (timestamp, amount), by history and index.
+    pub delegation_checkpoints: LookupMap<(DelegationHistory, u64), (Timestamp, Balance)>,
+    /// Index of the first kept checkpoint and after the last one, per history.
+    pub delegation_checkpoint_ranges: LookupMap<DelegationHistory, (u64, u64)>,
+    /// Sum of square roots of all delegations, used for quadratic voting.
+    pub total_quadratic_delegation_amount: Balance,
+    /// Amount delegated before checkpoints were kept, not yet counted in the quadratic total.
+    pub uncounted_delegation_amount: Balance,
+    /// Quadratic total counts all delegations since this time, it's incomplete before.
+    pub quadratic_total_since: Timestamp,
+    /// Proposals before this id can't be voted on anymore, so history of delegations isn't kept for them.
+    pub first_live_proposal_id: u64,
 
     /// Last available id for the proposals.
     pub last_proposal_id: u64,
@@ -70,36 +119,96 @@ pub struct Contract {
     /// Count of claims per bounty.
     pub bounty_claims_count: LookupMap<u64, u32>,
 
+    /// Last available id for the stream.
+    pub last_stream_id: u64,
+    /// Streams map from ID to stream information.
+    pub streams: LookupMap<u64, VersionedStream>,
+
+    /// Last available id for the recurring transfer.
+    pub last_recurring_transfer_id: u64,
+    /// Recurring transfers map from ID to the schedule.
+    pub recurring_transfers: LookupMap<u64, VersionedRecurringTransfer>,
+
+    /// Id of each large group role, members are stored under it.
+    /// Id is removed with the role, so members of removed or renamed roles are left behind.
+    pub large_group_ids: LookupMap<String, u64>,
+    /// Last id given to a large group role.
+    pub last_large_group_id: u64,
+    /// Members of large group roles, as pairs of role id and account.
+    pub large_group_members: LookupSet<(u64, AccountId)>,
+    /// Number of members per large group role id.
+    pub large_group_sizes: LookupMap<u64, u64>,
+
     /// Large blob storage.
     pub blobs: LookupMap<CryptoHash, AccountId>,
 }
 
+/// State of the previous version of the contract, only read by `migrate`.
+#[derive(BorshSerialize, BorshDeserialize)]
+pub struct OldContract {
+    pub config: LazyOption<Config>,
+    pub policy: LazyOption<VersionedPolicy>,
+    pub locked_amount: Balance,
+    pub staking_id: Option<AccountId>,
+    pub total_delegation_amount: Balance,
+    pub delegations: LookupMap<AccountId, Balance>,
+    pub last_proposal_id: u64,
+    pub proposals: LookupMap<u64, VersionedProposal>,
+    pub last_bounty_id: u64,
+    pub bounties: LookupMap<u64, VersionedBounty>,
+    pub bounty_claimers: LookupMap<AccountId, Vec<BountyClaim>>,
+    pub bounty_claims_count: LookupMap<u64, u32>,
+    pub blobs: LookupMap<CryptoHash, AccountId>,
+}
+
 #[near_bindgen]
 impl Contract {
     #[init]
     pub fn new(config: Config, policy: VersionedPolicy) -> Self {
+        let policy = policy.upgrade();
+        // Large groups start empty, members are added via `AddMemberToRole` proposals.
+        policy.clone().to_policy().assert_valid();
         Self {
             config: LazyOption::new(StorageKeys::Config, Some(&config)),
-            policy: LazyOption::new(StorageKeys::Policy, Some(&policy.upgrade())),
+            policy: LazyOption::new(StorageKeys::Policy, Some(&policy)),
+            treasury: LookupMap::new(StorageKeys::Treasury),
+            treasury_tokens: Vector::new(StorageKeys::TreasuryTokens),
+            spendings: LookupMap::new(StorageKeys::Spendings),
             staking_id: None,
             total_delegation_amount: 0,
             delegations: LookupMap::new(StorageKeys::Delegations),
+            delegation_checkpoints: LookupMap::new(StorageKeys::DelegationCheckpoints),
+            delegation_checkpoint_ranges: LookupMap::new(StorageKeys::DelegationCheckpointRanges),
+            total_quadratic_delegation_amount: 0,
+            uncounted_delegation_amount: 0,
+            quadratic_total_since: 0,
+            first_live_proposal_id: 0,
             last_proposal_id: 0,
             proposals: LookupMap::new(StorageKeys::Proposals),
             last_bounty_id: 0,
             bounties: LookupMap::new(StorageKeys::Bounties),
             bounty_claimers: LookupMap::new(StorageKeys::BountyClaimers),
             bounty_claims_count: LookupMap::new(StorageKeys::BountyClaimCounts),
+            last_stream_id: 0,
+            streams: LookupMap::new(StorageKeys::Streams),
+            last_recurring_transfer_id: 0,
+            recurring_transfers: LookupMap::new(StorageKeys::RecurringTransfers),
+            large_group_ids: LookupMap::new(StorageKeys::LargeGroupIds),
+            last_large_group_id: 0,
+            large_group_members: LookupSet::new(StorageKeys::LargeGroupMembers),
+            large_group_sizes: LookupMap::new(StorageKeys::LargeGroupSizes),
             blobs: LookupMap::new(StorageKeys::Blobs),
             // TODO: only accounts for contract but not for this state object. Can just add fixed size of it.
             locked_amount: env::storage_byte_cost() * (env::storage_usage() as u128),
+            first_locked_bond_id: 0,
         }
     }
 
     /// Should only be called by this contract on migration.
-    /// This is NOOP implementation. KEEP IT if you haven't changed contract state.
-    /// If you have changed state, you need to implement migration from old state (keep the old struct with different name to deserialize it first).
-    /// After migrate goes live on MainNet, return this implementation for next updates.
+    /// Migrates from `OldContract` state: adds new collections and upgrades the stored policy.
+    /// Proposals are upgraded when they are read, delegations made before are added to the history when they change.
+    /// Quadratic total is incomplete until all delegations made before are counted, see `count_delegations`.
+    /// After migrate goes live on MainNet, return NOOP implementation reading `Contract` for next updates.
     #[init(ignore_state)]
     pub fn migrate() -> Self {
         assert_eq!(
@@ -107,8 +216,46 @@ impl Contract {
             env::current_account_id(),
             "ERR_NOT_ALLOWED"
         );
-        let this: Contract = env::state_read().expect("ERR_CONTRACT_IS_NOT_INITIALIZED");
-        this
+        let old: OldContract = env::state_read().expect("ERR_CONTRACT_IS_NOT_INITIALIZED");
+        let mut policy = old.policy;
+        policy.set(&policy.get().unwrap().upgrade());
+        Self {
+            config: old.config,
+            policy,
+            locked_amount: old.locked_amount,
+            first_locked_bond_id: old.last_proposal_id,
+            treasury: LookupMap::new(StorageKeys::Treasury),
+            treasury_tokens: Vector::new(StorageKeys::TreasuryTokens),
+            spendings: LookupMap::new(StorageKeys::Spendings),
+            staking_id: old.staking_id,
+            total_delegation_amount: old.total_delegation_amount,
+            delegations: old.delegations,
+            delegation_checkpoints: LookupMap::new(StorageKeys::DelegationCheckpoints),
+            delegation_checkpoint_ranges: LookupMap::new(StorageKeys::DelegationCheckpointRanges),
+            total_quadratic_delegation_amount: 0,
+            uncounted_delegation_amount: old.total_delegation_amount,
+            quadratic_total_since: if old.total_delegation_amount == 0 {
+                0
+            } else {
+                Timestamp::MAX
+            },
+            first_live_proposal_id: 0,
+            last_proposal_id: old.last_proposal_id,
+            proposals: old.proposals,
+            last_bounty_id: old.last_bounty_id,
+            bounties: old.bounties,
+            bounty_claimers: old.bounty_claimers,
+            bounty_claims_count: old.bounty_claims_count,
+            last_stream_id: 0,
+            streams: LookupMap::new(StorageKeys::Streams),
+            last_recurring_transfer_id: 0,
+            recurring_transfers: LookupMap::new(StorageKeys::RecurringTransfers),
+            large_group_ids: LookupMap::new(StorageKeys::LargeGroupIds),
+            last_large_group_id: 0,
+            large_group_members: LookupSet::new(StorageKeys::LargeGroupMembers),
+            large_group_sizes: LookupMap::new(StorageKeys::LargeGroupSizes),
+            blobs: old.blobs,
+        }
     }
 
     /// Remove blob from contract storage and pay back to original storer.
@@ -200,17 +347,26 @@ pub extern "C" fn store_blob() {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
+    use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+    use near_sdk::json_types::{WrappedDuration, WrappedTimestamp};
     use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::{testing_env, MockedBlockchain};
+    use near_sdk::{testing_env, MockedBlockchain, PromiseOrValue, PromiseResult};
     use near_sdk_sim::to_yocto;
 
-    use crate::proposals::ProposalStatus;
+    use crate::policy::{PolicyV1, RolePermissionV1, VotePolicyV1, WeightOrRatio};
+    use crate::proposals::{ActionCall, ProposalKindV1, ProposalStatus, ProposalV1};
     use crate::types::BASE_TOKEN;
 
     use super::*;
 
     fn create_proposal(context: &mut VMContextBuilder, contract: &mut Contract) -> u64 {
-        testing_env!(context.attached_deposit(to_yocto("1")).build());
+        // Enough $NEAR to reserve for the transfer once it's approved.
+        testing_env!(context
+            .account_balance(to_yocto("1000"))
+            .attached_deposit(to_yocto("1"))
+            .build());
         contract.add_proposal(ProposalInput {
             description: "test".to_string(),
             kind: ProposalKind::Transfer {
@@ -262,6 +418,7 @@ mod tests {
             kind: ProposalKind::AddMemberToRole {
                 member_id: accounts(2).into(),
                 role: "council".to_string(),
+                term: None,
             },
         });
     }
@@ -295,6 +452,107 @@ mod tests {
         assert_eq!(contract.get_proposals(0, 10).len(), 0);
     }
 
+    fn add_term_member(context: &mut VMContextBuilder, contract: &mut Contract, term: u64) {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::AddMemberToRole {
+                member_id: accounts(2).into(),
+                role: "council".to_string(),
+                term: Some(WrappedDuration::from(term)),
+            },
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+    }
+
+    #[test]
+    fn test_term_membership() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        add_term_member(&mut context, &mut contract, 1_000);
+        assert_eq!(contract.get_policy().roles[1].kind.get_role_size(), Some(2));
+        assert!(contract
+            .get_expiring_members(WrappedDuration::from(500))
+            .is_empty());
+        let expiring = contract.get_expiring_members(WrappedDuration::from(1_000));
+        let member_id: AccountId = accounts(2).into();
+        assert_eq!(expiring[0].account_id, member_id);
+        assert_eq!(expiring[0].expires_at, WrappedTimestamp::from(1_000));
+
+        testing_env!(context.block_timestamp(1_000).build());
+        assert_eq!(contract.get_policy().roles[1].kind.get_role_size(), Some(1));
+        assert!(contract
+            .get_expiring_members(WrappedDuration::from(1_000))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_term_membership_max() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        testing_env!(context.block_timestamp(1).build());
+        add_term_member(&mut context, &mut contract, u64::MAX);
+        let expiring = contract.get_expiring_members(WrappedDuration::from(u64::MAX));
+        assert_eq!(expiring[0].expires_at, WrappedTimestamp::from(u64::MAX));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PERMISSION_DENIED")]
+    fn test_term_membership_expired() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        add_term_member(&mut context, &mut contract, 1_000);
+        let id = create_proposal(&mut context, &mut contract);
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(1_000)
+            .build());
+        contract.act_proposal(id, Action::VoteApprove, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_VOTERS")]
+    fn test_new_without_voters() {
+        let context = VMContextBuilder::new();
+        testing_env!(context.build());
+        Contract::new(Config::test_config(), VersionedPolicy::Default(vec![]));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_DUPLICATE_ROLE:council")]
+    fn test_change_policy_invalid() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        let mut policy = contract.get_policy();
+        policy.roles.push(policy.roles[1].clone());
+        testing_env!(context.attached_deposit(to_yocto("1")).build());
+        contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::ChangePolicy {
+                policy: VersionedPolicy::Current(policy),
+            },
+        });
+    }
+
     #[test]
     fn test_vote_expired_proposal() {
         let mut context = VMContextBuilder::new();
@@ -310,6 +568,1090 @@ mod tests {
         contract.act_proposal(id, Action::VoteApprove, None);
     }
 
+    #[test]
+    fn test_execution_delay() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![accounts(1).into()]).upgrade();
+        policy
+            .to_policy_mut()
+            .execution_delay
+            .insert("transfer".to_string(), WrappedDuration::from(1_000));
+        let mut contract = Contract::new(Config::test_config(), policy);
+        let id = create_proposal(&mut context, &mut contract);
+        contract.act_proposal(id, Action::VoteApprove, None);
+        let proposal = contract.get_proposal(id).proposal;
+        assert_eq!(proposal.status, ProposalStatus::Queued);
+        assert_eq!(proposal.executable_at, Some(WrappedTimestamp::from(1_000)));
+        testing_env!(context.block_timestamp(1_000).build());
+        contract.execute_proposal(id);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PROPOSAL_NOT_EXECUTABLE_YET")]
+    fn test_execute_proposal_too_early() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![accounts(1).into()]).upgrade();
+        policy
+            .to_policy_mut()
+            .execution_delay
+            .insert("transfer".to_string(), WrappedDuration::from(1_000));
+        let mut contract = Contract::new(Config::test_config(), policy);
+        let id = create_proposal(&mut context, &mut contract);
+        contract.act_proposal(id, Action::VoteApprove, None);
+        contract.execute_proposal(id);
+    }
+
+    #[test]
+    fn test_failed_execution_retry() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        let id = create_proposal(&mut context, &mut contract);
+        contract.act_proposal(id, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .current_account_id(accounts(0))
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.on_proposal_callback(id);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Failed
+        );
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.act_proposal(id, Action::Retry, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    fn test_token_transfer_reserves_treasury() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.test_track_token(&accounts(3).into());
+        contract.ft_on_transfer(accounts(2), U128(100), "".to_string());
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Transfer {
+                token_id: accounts(3).into(),
+                receiver_id: accounts(2).into(),
+                amount: U128(80),
+                msg: None,
+            },
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(20));
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .current_account_id(accounts(0))
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(vec![])]
+        );
+        contract.on_proposal_callback(id);
+        assert_eq!(
+            contract.get_treasury(0, 10),
+            vec![(
+                accounts(3).into(),
+                TokenBalance {
+                    amount: U128(20),
+                    reserved: U128(0),
+                }
+            )]
+        );
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(20));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PROPOSAL_EXECUTING")]
+    fn test_remove_executing_transfer() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let mut policy = VersionedPolicy::Default(vec![accounts(1).into()]).upgrade();
+        policy.to_policy_mut().roles[1]
+            .permissions
+            .insert("*:RemoveProposal".to_string());
+        let mut contract = Contract::new(Config::test_config(), policy);
+        contract.test_track_token(&accounts(3).into());
+        contract.ft_on_transfer(accounts(2), U128(100), "".to_string());
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Transfer {
+                token_id: accounts(3).into(),
+                receiver_id: accounts(2).into(),
+                amount: U128(80),
+                msg: None,
+            },
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+        // Callback of the transfer is still pending.
+        contract.act_proposal(id, Action::RemoveProposal, None);
+    }
+
+    #[test]
+    fn test_token_transfer_failed_releases_treasury() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.test_track_token(&accounts(3).into());
+        contract.ft_on_transfer(accounts(2), U128(100), "".to_string());
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Transfer {
+                token_id: accounts(3).into(),
+                receiver_id: accounts(2).into(),
+                amount: U128(80),
+                msg: None,
+            },
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .current_account_id(accounts(0))
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.on_proposal_callback(id);
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(100));
+        // Reserved again on retry.
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.act_proposal(id, Action::Retry, None);
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(20));
+    }
+
+    #[test]
+    fn test_token_transfer_not_enough_tokens() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.test_track_token(&accounts(3).into());
+        contract.ft_on_transfer(accounts(2), U128(100), "".to_string());
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Transfer {
+                token_id: accounts(3).into(),
+                receiver_id: accounts(2).into(),
+                amount: U128(150),
+                msg: None,
+            },
+        });
+        // Approving vote doesn't fail, proposal is retried once there are enough tokens.
+        contract.act_proposal(id, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Failed
+        );
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(100));
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.ft_on_transfer(accounts(2), U128(50), "".to_string());
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.act_proposal(id, Action::Retry, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(0));
+    }
+
+    #[test]
+    fn test_multi_function_call_failed_step() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        let action: ActionCall = near_sdk::serde_json::from_str(
+            r#"{"method_name": "test", "args": "", "deposit": "0", "gas": "10000000000000"}"#,
+        )
+        .unwrap();
+        testing_env!(context.attached_deposit(to_yocto("1")).build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::MultiFunctionCall {
+                calls: vec![
+                    (accounts(2), vec![action.clone()]),
+                    (accounts(3), vec![action]),
+                ],
+            },
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+        let callback_context = |context: &mut VMContextBuilder, result: PromiseResult| {
+            testing_env!(
+                context
+                    .predecessor_account_id(accounts(0))
+                    .current_account_id(accounts(0))
+                    .build(),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                vec![result]
+            );
+        };
+        callback_context(&mut context, PromiseResult::Successful(vec![]));
+        contract.on_call_step_callback(id, 0);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+        callback_context(&mut context, PromiseResult::Failed);
+        contract.on_call_step_callback(id, 1);
+        let proposal = contract.get_proposal(id).proposal;
+        assert_eq!(proposal.status, ProposalStatus::Failed);
+        assert_eq!(proposal.failed_step, Some(1));
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.act_proposal(id, Action::Retry, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_GAS_OVERFLOW")]
+    fn test_multi_function_call_gas_overflow() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        let action: ActionCall = near_sdk::serde_json::from_str(
+            r#"{"method_name": "test", "args": "", "deposit": "0", "gas": "18446744073709551615"}"#,
+        )
+        .unwrap();
+        testing_env!(context.attached_deposit(to_yocto("1")).build());
+        contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::MultiFunctionCall {
+                calls: vec![(accounts(2), vec![action])],
+            },
+        });
+    }
+
+    fn setup_move_to_hub(context: &mut VMContextBuilder) -> (Contract, u64) {
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![accounts(1).into()]).upgrade();
+        policy.to_policy_mut().hub_id = Some(accounts(4).into());
+        let mut contract = Contract::new(Config::test_config(), policy);
+        testing_env!(context.attached_deposit(to_yocto("1")).build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Vote,
+        });
+        (contract, id)
+    }
+
+    #[test]
+    fn test_move_to_hub() {
+        let mut context = VMContextBuilder::new();
+        let (mut contract, id) = setup_move_to_hub(&mut context);
+        let move_id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::MoveToHub { proposal_id: id },
+        });
+        contract.act_proposal(move_id, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Moved
+        );
+        let callback_context = |context: &mut VMContextBuilder, result: PromiseResult| {
+            testing_env!(
+                context
+                    .predecessor_account_id(accounts(0))
+                    .current_account_id(accounts(0))
+                    .build(),
+                Default::default(),
+                Default::default(),
+                Default::default(),
+                vec![result]
+            );
+        };
+        // Hub takes bonds in tokens, which this DAO can't attach.
+        callback_context(
+            &mut context,
+            PromiseResult::Successful(
+                br#"{"proposal_bond": "10", "proposal_bond_token": "token.near"}"#.to_vec(),
+            ),
+        );
+        contract.on_hub_policy_callback(id, accounts(4).into());
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::InProgress
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let move_id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::MoveToHub { proposal_id: id },
+        });
+        contract.act_proposal(move_id, Action::VoteApprove, None);
+        callback_context(
+            &mut context,
+            PromiseResult::Successful(br#"{"proposal_bond": "10"}"#.to_vec()),
+        );
+        assert!(matches!(
+            contract.on_hub_policy_callback(id, accounts(4).into()),
+            PromiseOrValue::Promise(_)
+        ));
+        callback_context(&mut context, PromiseResult::Successful(b"5".to_vec()));
+        contract.on_move_proposal_callback(id, accounts(4).into());
+        let proposal = contract.get_proposal(id).proposal;
+        assert_eq!(proposal.status, ProposalStatus::Moved);
+        assert_eq!(proposal.moved_to, Some((accounts(4).into(), 5)));
+    }
+
+    #[test]
+    fn test_move_to_hub_bond_not_locked() {
+        let mut context = VMContextBuilder::new();
+        let (mut contract, id) = setup_move_to_hub(&mut context);
+        let available = contract.get_available_amount().0;
+        let move_id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::MoveToHub { proposal_id: id },
+        });
+        // Bonds are locked until they are returned.
+        assert_eq!(contract.get_available_amount().0, available - to_yocto("1"));
+        contract.act_proposal(move_id, Action::VoteApprove, None);
+        // Hub bond can't be paid from bonds of the proposals.
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .current_account_id(accounts(0))
+            .build());
+        let hub_policy = format!(
+            r#"{{"proposal_bond": "{}"}}"#,
+            contract.get_available_amount().0 + 1
+        );
+        testing_env!(
+            context.build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(hub_policy.into_bytes())]
+        );
+        contract.on_hub_policy_callback(id, accounts(4).into());
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::InProgress
+        );
+    }
+
+    #[test]
+    fn test_move_delayed_proposal_to_hub() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![
+            accounts(1).into(),
+            accounts(2).into(),
+            accounts(3).into(),
+        ])
+        .upgrade();
+        policy.to_policy_mut().hub_id = Some(accounts(4).into());
+        let vote_policy = VotePolicy {
+            optimistic: Some(OptimisticApproval {
+                threshold: WeightOrRatio::Weight(U128(1)),
+                grace_period: WrappedDuration::from(100),
+            }),
+            ..policy.to_policy_mut().default_vote_policy.clone()
+        };
+        policy.to_policy_mut().roles[1]
+            .vote_policy
+            .insert("vote".to_string(), vote_policy);
+        let mut contract = Contract::new(Config::test_config(), policy);
+        testing_env!(context.attached_deposit(to_yocto("1")).build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Vote,
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Delay
+        );
+        let move_id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::MoveToHub { proposal_id: id },
+        });
+        contract.act_proposal(move_id, Action::VoteApprove, None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.act_proposal(move_id, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Moved
+        );
+        // Proposal goes back to waiting for the grace period if it can't be moved.
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .current_account_id(accounts(0))
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.on_hub_policy_callback(id, accounts(4).into());
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Delay
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PERMISSION_DENIED")]
+    fn test_move_to_hub_without_vote() {
+        let mut context = VMContextBuilder::new();
+        let (mut contract, id) = setup_move_to_hub(&mut context);
+        contract.act_proposal(id, Action::MoveToHub, None);
+    }
+
+    /// Sets up proposal that 2 of 4 council members rejected, where council can change votes if given.
+    fn setup_finalize_early(context: &mut VMContextBuilder, change_votes: bool) -> (Contract, u64) {
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![
+            accounts(1).into(),
+            accounts(2).into(),
+            accounts(3).into(),
+            accounts(4).into(),
+        ])
+        .upgrade();
+        if !change_votes {
+            policy.to_policy_mut().roles[1]
+                .permissions
+                .retain(|permission| permission != "*:ChangeVote" && permission != "*:Unvote");
+        }
+        let mut contract = Contract::new(Config::test_config(), policy);
+        let id = create_proposal(context, &mut contract);
+        contract.act_proposal(id, Action::VoteReject, None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.act_proposal(id, Action::VoteReject, None);
+        // 2 of 4 rejected: not enough to reject.
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::InProgress
+        );
+        (contract, id)
+    }
+
+    #[test]
+    fn test_finalize_early() {
+        let mut context = VMContextBuilder::new();
+        let (mut contract, id) = setup_finalize_early(&mut context, false);
+        // Approval needs 3 votes now out of 2 left.
+        contract.act_proposal(id, Action::Finalize, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Rejected
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PROPOSAL_NOT_EXPIRED")]
+    fn test_finalize_early_changeable_votes() {
+        let mut context = VMContextBuilder::new();
+        let (mut contract, id) = setup_finalize_early(&mut context, true);
+        // Rejected votes can still be changed to approve.
+        contract.act_proposal(id, Action::Finalize, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PROPOSAL_NOT_EXPIRED")]
+    fn test_finalize_early_optimistic() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![
+            accounts(1).into(),
+            accounts(2).into(),
+            accounts(3).into(),
+            accounts(4).into(),
+        ])
+        .upgrade();
+        policy.to_policy_mut().roles[1]
+            .permissions
+            .retain(|permission| permission != "*:ChangeVote" && permission != "*:Unvote");
+        policy.to_policy_mut().default_vote_policy.optimistic = Some(OptimisticApproval {
+            threshold: WeightOrRatio::Weight(U128(1)),
+            grace_period: WrappedDuration::from(100),
+        });
+        let mut contract = Contract::new(Config::test_config(), policy);
+        let id = create_proposal(&mut context, &mut contract);
+        contract.act_proposal(id, Action::VoteRemove, None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.act_proposal(id, Action::VoteRemove, None);
+        // Approval needs 3 votes out of 2 left, but a single one starts the grace period.
+        contract.act_proposal(id, Action::Finalize, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PROPOSAL_NOT_EXPIRED")]
+    fn test_finalize_undecided() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into(), accounts(2).into()]),
+        );
+        let id = create_proposal(&mut context, &mut contract);
+        contract.act_proposal(id, Action::Finalize, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PROPOSAL_NOT_EXPIRED")]
+    fn test_finalize_no_approvers() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![accounts(1).into()]).upgrade();
+        // Council can only vote on `vote` proposals, so no one can approve transfers.
+        policy.to_policy_mut().roles[1].permissions = vec![
+            "*:AddProposal".to_string(),
+            "*:Finalize".to_string(),
+            "vote:VoteApprove".to_string(),
+        ]
+        .into_iter()
+        .collect();
+        let mut contract = Contract::new(Config::test_config(), policy);
+        let id = create_proposal(&mut context, &mut contract);
+        contract.act_proposal(id, Action::Finalize, None);
+    }
+
+    fn setup_veto(context: &mut VMContextBuilder) -> (Contract, u64) {
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![
+            accounts(1).into(),
+            accounts(2).into(),
+            accounts(3).into(),
+        ])
+        .upgrade();
+        policy.to_policy_mut().default_vote_policy.veto_threshold =
+            Some(WeightOrRatio::Weight(U128(1)));
+        let mut contract = Contract::new(Config::test_config(), policy);
+        let id = create_proposal(context, &mut contract);
+        (contract, id)
+    }
+
+    #[test]
+    fn test_veto_not_vetoed() {
+        let mut context = VMContextBuilder::new();
+        let (mut contract, id) = setup_veto(&mut context);
+        // Can't be rejected early, as it passes by default.
+        assert!(!contract.get_policy().is_approval_unreachable(
+            &contract.get_proposal(id).proposal,
+            &|_: &WeightKind| 0,
+            &|_: &ProposalKind| None
+        ));
+        testing_env!(context
+            .block_timestamp(1_000_000_000 * 24 * 60 * 60 * 8)
+            .build());
+        contract.act_proposal(id, Action::Finalize, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    fn test_veto_vetoed() {
+        let mut context = VMContextBuilder::new();
+        let (mut contract, id) = setup_veto(&mut context);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.act_proposal(id, Action::VoteReject, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::InProgress
+        );
+        testing_env!(context
+            .block_timestamp(1_000_000_000 * 24 * 60 * 60 * 8)
+            .build());
+        contract.act_proposal(id, Action::Finalize, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Rejected
+        );
+    }
+
+    fn setup_optimistic(context: &mut VMContextBuilder) -> (Contract, u64) {
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![
+            accounts(1).into(),
+            accounts(2).into(),
+            accounts(3).into(),
+        ])
+        .upgrade();
+        policy.to_policy_mut().default_vote_policy.optimistic = Some(OptimisticApproval {
+            threshold: WeightOrRatio::Weight(U128(1)),
+            grace_period: WrappedDuration::from(100),
+        });
+        let mut contract = Contract::new(Config::test_config(), policy);
+        let id = create_proposal(context, &mut contract);
+        contract.act_proposal(id, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Delay
+        );
+        (contract, id)
+    }
+
+    #[test]
+    fn test_optimistic_approval() {
+        let mut context = VMContextBuilder::new();
+        let (mut contract, id) = setup_optimistic(&mut context);
+        testing_env!(context.block_timestamp(100).build());
+        contract.act_proposal(id, Action::Finalize, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_GRACE_PERIOD_NOT_OVER")]
+    fn test_optimistic_approval_too_early() {
+        let mut context = VMContextBuilder::new();
+        let (mut contract, id) = setup_optimistic(&mut context);
+        testing_env!(context.block_timestamp(50).build());
+        contract.act_proposal(id, Action::Finalize, None);
+    }
+
+    #[test]
+    fn test_optimistic_approval_vote_after_period() {
+        let mut context = VMContextBuilder::new();
+        let (mut contract, id) = setup_optimistic(&mut context);
+        // Vote after the voting period doesn't count, grace period is over.
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(1_000_000_000 * 24 * 60 * 60 * 8)
+            .build());
+        contract.act_proposal(id, Action::VoteReject, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    fn test_optimistic_approval_rejected() {
+        let mut context = VMContextBuilder::new();
+        let (mut contract, id) = setup_optimistic(&mut context);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.act_proposal(id, Action::VoteReject, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::InProgress
+        );
+        // Now requires full threshold.
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.act_proposal(id, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    fn test_change_vote_and_unvote() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into(), accounts(2).into()]),
+        );
+        let id = create_proposal(&mut context, &mut contract);
+        contract.act_proposal(id, Action::VoteApprove, None);
+        contract.act_proposal(id, Action::ChangeVote(Vote::Reject), None);
+        let proposal = contract.get_proposal(id).proposal;
+        assert_eq!(proposal.vote_counts["council"], [0, 1, 0]);
+        assert_eq!(proposal.status, ProposalStatus::InProgress);
+        contract.act_proposal(id, Action::Unvote, None);
+        let proposal = contract.get_proposal(id).proposal;
+        assert_eq!(proposal.vote_counts["council"], [0, 0, 0]);
+        assert!(proposal.votes.is_empty());
+        // Can vote again after withdrawing the vote.
+        contract.act_proposal(id, Action::VoteApprove, None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.act_proposal(id, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PERMISSION_DENIED")]
+    fn test_change_vote_not_allowed() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy =
+            VersionedPolicy::Default(vec![accounts(1).into(), accounts(2).into()]).upgrade();
+        // Council can't vote to remove proposals.
+        policy.to_policy_mut().roles[1].permissions = vec![
+            "*:AddProposal".to_string(),
+            "*:VoteApprove".to_string(),
+            "*:VoteReject".to_string(),
+            "*:ChangeVote".to_string(),
+        ]
+        .into_iter()
+        .collect();
+        let mut contract = Contract::new(Config::test_config(), policy);
+        let id = create_proposal(&mut context, &mut contract);
+        contract.act_proposal(id, Action::VoteApprove, None);
+        contract.act_proposal(id, Action::ChangeVote(Vote::Remove), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_VOTE")]
+    fn test_unvote_without_vote() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into(), accounts(2).into()]),
+        );
+        let id = create_proposal(&mut context, &mut contract);
+        contract.act_proposal(id, Action::Unvote, None);
+    }
+
+    /// Writes state of the previous version with given delegations and a proposal in progress.
+    fn write_old_contract(delegations: &[(AccountId, Balance)]) {
+        let council: AccountId = accounts(1).into();
+        let policy = PolicyV1 {
+            roles: vec![RolePermissionV1 {
+                name: "council".to_string(),
+                kind: RoleKind::Group(vec![council].into_iter().collect()),
+                permissions: vec!["*:*".to_string()].into_iter().collect(),
+                vote_policy: HashMap::default(),
+            }],
+            default_vote_policy: VotePolicyV1 {
+                weight_kind: WeightKind::RoleWeight,
+                quorum: U128(0),
+                threshold: WeightOrRatio::Ratio(1, 2),
+            },
+            proposal_bond: U128(10),
+            proposal_period: WrappedDuration::from(1_000),
+            bounty_bond: U128(10),
+            bounty_forgiveness_period: WrappedDuration::from(1_000),
+        };
+        let mut old = OldContract {
+            config: LazyOption::new(StorageKeys::Config, Some(&Config::test_config())),
+            policy: LazyOption::new(StorageKeys::Policy, Some(&VersionedPolicy::V1(policy))),
+            locked_amount: 100,
+            staking_id: Some(accounts(3).into()),
+            total_delegation_amount: delegations.iter().map(|(_, amount)| amount).sum(),
+            delegations: LookupMap::new(StorageKeys::Delegations),
+            last_proposal_id: 1,
+            proposals: LookupMap::new(StorageKeys::Proposals),
+            last_bounty_id: 0,
+            bounties: LookupMap::new(StorageKeys::Bounties),
+            bounty_claimers: LookupMap::new(StorageKeys::BountyClaimers),
+            bounty_claims_count: LookupMap::new(StorageKeys::BountyClaimCounts),
+            blobs: LookupMap::new(StorageKeys::Blobs),
+        };
+        for (account_id, amount) in delegations {
+            old.delegations.insert(account_id, amount);
+        }
+        old.proposals.insert(
+            &0,
+            &VersionedProposal::Default(ProposalV1 {
+                proposer: accounts(1).into(),
+                description: "test".to_string(),
+                kind: ProposalKindV1::Vote,
+                status: ProposalStatus::InProgress,
+                vote_counts: HashMap::default(),
+                votes: HashMap::default(),
+                submission_time: WrappedTimestamp::from(0),
+            }),
+        );
+        env::state_write(&old);
+    }
+
+    #[test]
+    fn test_migrate() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .current_account_id(accounts(0))
+            .build());
+        let user: AccountId = accounts(2).into();
+        write_old_contract(&[(user.clone(), 16)]);
+
+        let mut contract = Contract::migrate();
+        assert_eq!(contract.get_policy().proposal_bond, U128(10));
+        assert!(matches!(
+            contract.policy.get().unwrap(),
+            VersionedPolicy::Current(_)
+        ));
+        assert_eq!(contract.get_proposal(0).proposal.bond, U128(10));
+        assert_eq!(contract.get_user_weight_at(&user, 0), 16);
+        assert_eq!(contract.get_total_delegation_amount_at(0), 16);
+        // Delegation before the upgrade is kept in the history when it changes.
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(DELEGATION_STORAGE as Balance * env::storage_byte_cost())
+            .block_timestamp(100)
+            .build());
+        contract.undelegate(&user, U128(6));
+        assert_eq!(contract.get_user_weight_at(&user, 50), 16);
+        assert_eq!(contract.get_user_weight_at(&user, 100), 10);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let locked_amount = contract.locked_amount;
+        contract.act_proposal(0, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(0).proposal.status,
+            ProposalStatus::Approved
+        );
+        // Bond of the proposal added before the upgrade wasn't locked, so returning it doesn't unlock.
+        assert_eq!(contract.locked_amount, locked_amount);
+    }
+
+    /// Migrates contract with delegations made before the upgrade and sets quadratic voting by delegates.
+    fn migrate_quadratic(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context
+            .predecessor_account_id(accounts(0))
+            .current_account_id(accounts(0))
+            .build());
+        write_old_contract(&[(accounts(2).into(), 16), (accounts(3).into(), 9)]);
+        let mut contract = Contract::migrate();
+        let mut policy = contract.policy.get().unwrap();
+        policy.to_policy_mut().roles.push(RolePermission {
+            name: "delegates".to_string(),
+            kind: RoleKind::Member(1),
+            permissions: vec!["*:VoteApprove".to_string()].into_iter().collect(),
+            scoped_permissions: vec![],
+            vote_policy: HashMap::default(),
+            allowances: HashMap::default(),
+            parent: None,
+        });
+        policy.to_policy_mut().default_vote_policy.weight_kind = WeightKind::QuadraticTokenWeight;
+        contract.policy.set(&policy);
+        contract
+    }
+
+    #[test]
+    fn test_migrate_quadratic_vote() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = migrate_quadratic(&mut context);
+        testing_env!(context
+            .attached_deposit(2 * DELEGATION_STORAGE as Balance * env::storage_byte_cost())
+            .block_timestamp(100)
+            .build());
+        contract.count_delegations(vec![accounts(2).into(), accounts(3).into()]);
+        assert_eq!(contract.total_quadratic_delegation_amount, 7);
+        // Square root 3 of the first vote isn't over half of the total 7.
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.act_proposal(0, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(0).proposal.status,
+            ProposalStatus::InProgress
+        );
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.act_proposal(0, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(0).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_QUADRATIC_TOTAL_INCOMPLETE")]
+    fn test_migrate_quadratic_not_counted() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = migrate_quadratic(&mut context);
+        testing_env!(context
+            .attached_deposit(DELEGATION_STORAGE as Balance * env::storage_byte_cost())
+            .build());
+        contract.count_delegations(vec![accounts(3).into()]);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.act_proposal(0, Action::VoteApprove, None);
+    }
+
+    #[test]
+    fn test_proposal_v1() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.proposals.insert(
+            &0,
+            &VersionedProposal::Default(ProposalV1 {
+                proposer: accounts(1).into(),
+                description: "test".to_string(),
+                kind: ProposalKindV1::AddMemberToRole {
+                    member_id: accounts(2),
+                    role: "council".to_string(),
+                },
+                status: ProposalStatus::InProgress,
+                vote_counts: HashMap::default(),
+                votes: HashMap::default(),
+                submission_time: WrappedTimestamp::from(0),
+            }),
+        );
+        contract.last_proposal_id = 1;
+        let proposal = contract.get_proposal(0).proposal;
+        assert_eq!(proposal.bond, U128(to_yocto("1")));
+        assert_eq!(proposal.bond_token, BASE_TOKEN);
+        contract.act_proposal(0, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(0).proposal.status,
+            ProposalStatus::Approved
+        );
+        assert_eq!(contract.get_policy().roles[1].kind.get_role_size(), Some(2));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_VOTE_WEIGHT_UNKNOWN")]
+    fn test_proposal_v1_change_vote() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into(), accounts(2).into()]),
+        );
+        // Voted before the upgrade, when weights weren't recorded.
+        contract.proposals.insert(
+            &0,
+            &VersionedProposal::Default(ProposalV1 {
+                proposer: accounts(1).into(),
+                description: "test".to_string(),
+                kind: ProposalKindV1::Vote,
+                status: ProposalStatus::InProgress,
+                vote_counts: vec![("council".to_string(), [1, 0, 0])]
+                    .into_iter()
+                    .collect(),
+                votes: vec![(accounts(1).into(), Vote::Approve)]
+                    .into_iter()
+                    .collect(),
+                submission_time: WrappedTimestamp::from(0),
+            }),
+        );
+        contract.last_proposal_id = 1;
+        // Approval can't be counted again by changing the vote.
+        contract.act_proposal(0, Action::ChangeVote(Vote::Approve), None);
+    }
+
+    #[test]
+    fn test_unvote_with_new_role() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into(), accounts(2).into()]),
+        );
+        let id = create_proposal(&mut context, &mut contract);
+        contract.act_proposal(id, Action::VoteApprove, None);
+        // Role added after the vote has nothing counted.
+        let mut policy = contract.policy.get().unwrap();
+        policy.to_policy_mut().roles.push(RolePermission {
+            name: "reviewers".to_string(),
+            kind: RoleKind::Group(vec![accounts(1).into()].into_iter().collect()),
+            permissions: vec!["*:*".to_string()].into_iter().collect(),
+            scoped_permissions: vec![],
+            vote_policy: HashMap::default(),
+            allowances: HashMap::default(),
+            parent: None,
+        });
+        contract.policy.set(&policy);
+        contract.act_proposal(id, Action::Unvote, None);
+        let proposal = contract.get_proposal(id).proposal;
+        assert_eq!(proposal.vote_counts["council"], [0, 0, 0]);
+        assert_eq!(proposal.status, ProposalStatus::InProgress);
+    }
+
+    #[test]
+    fn test_bond_in_token() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![accounts(1).into()]).upgrade();
+        policy.to_policy_mut().proposal_bond_token = accounts(3).into();
+        policy.to_policy_mut().proposal_bond = U128(100);
+        let mut contract = Contract::new(Config::test_config(), policy);
+        let msg = near_sdk::serde_json::to_string(&ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Vote,
+        })
+        .unwrap();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let refund = contract.ft_on_transfer(accounts(1), U128(150), msg);
+        assert!(matches!(refund, PromiseOrValue::Value(U128(50))));
+        let proposal = contract.get_proposal(0).proposal;
+        assert_eq!(proposal.proposer, accounts(1).to_string());
+        assert_eq!(proposal.bond_token, accounts(3).to_string());
+        assert_eq!(proposal.bond, U128(100));
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.act_proposal(0, Action::VoteApprove, None);
+        assert_eq!(
+            contract.get_proposal(0).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_WRONG_BOND_TOKEN")]
+    fn test_bond_in_token_not_attached() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![accounts(1).into()]).upgrade();
+        policy.to_policy_mut().proposal_bond_token = accounts(3).into();
+        let mut contract = Contract::new(Config::test_config(), policy);
+        create_proposal(&mut context, &mut contract);
+    }
+
     #[test]
     #[should_panic(expected = "ERR_ALREADY_VOTED")]
     fn test_vote_twice() {
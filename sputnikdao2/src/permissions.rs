@@ -0,0 +1,166 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::AccountId;
+
+use crate::proposals::ProposalKind;
+use crate::types::Action;
+
+/// Condition on the payload of the proposal kind.
+/// Proposal kinds that don't have the constrained field don't match.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+#[serde(crate = "near_sdk::serde")]
+pub enum PermissionCondition {
+    /// Token of `Transfer`, `Stream`, `RecurringTransfer` or `AddBounty` is given one ("" for $NEAR).
+    TokenId(AccountId),
+    /// Total amount of `Transfer`, `Stream`, `RecurringTransfer` or `AddBounty` is at most given one.
+    MaxAmount(U128),
+    /// Receiver of `Transfer`, `Stream`, `RecurringTransfer`, `BountyDone`, `FunctionCall`
+    /// or `UpgradeRemote`, or every receiver of `MultiFunctionCall` is given one.
+    ReceiverId(AccountId),
+    /// Role of `AddMemberToRole` or `RemoveMemberFromRole` is given one.
+    Role(String),
+}
+
+impl PermissionCondition {
+    /// Checks if given proposal kind satisfies this condition.
+    /// `ActionPoll` satisfies it if every one of its options does.
+    pub fn matches(&self, proposal_kind: &ProposalKind) -> bool {
+        if let ProposalKind::ActionPoll { options, .. } = proposal_kind {
+            return options.iter().all(|option| self.matches(&option.kind));
+        }
+        match self {
+            PermissionCondition::TokenId(account_id) => match proposal_kind {
+                ProposalKind::Transfer { token_id, .. }
+                | ProposalKind::Stream { token_id, .. }
+                | ProposalKind::RecurringTransfer { token_id, .. } => token_id == account_id,
+                ProposalKind::AddBounty { bounty } => &bounty.token == account_id,
+                _ => false,
+            },
+            PermissionCondition::MaxAmount(max_amount) => match proposal_kind {
+                ProposalKind::Transfer { amount, .. } | ProposalKind::Stream { amount, .. } => {
+                    amount.0 <= max_amount.0
+                }
+                ProposalKind::RecurringTransfer { amount, times, .. } => {
+                    amount.0.saturating_mul(*times as u128) <= max_amount.0
+                }
+                ProposalKind::AddBounty { bounty } => {
+                    bounty.amount.0.saturating_mul(bounty.times as u128) <= max_amount.0
+                }
+                _ => false,
+            },
+            PermissionCondition::ReceiverId(account_id) => match proposal_kind {
+                ProposalKind::Transfer { receiver_id, .. }
+                | ProposalKind::Stream { receiver_id, .. }
+                | ProposalKind::RecurringTransfer { receiver_id, .. }
+                | ProposalKind::BountyDone { receiver_id, .. }
+                | ProposalKind::FunctionCall { receiver_id, .. }
+                | ProposalKind::UpgradeRemote { receiver_id, .. } => {
+                    receiver_id.as_ref() == account_id
+                }
+                ProposalKind::MultiFunctionCall { calls } => calls
+                    .iter()
+                    .all(|(receiver_id, _)| receiver_id.as_ref() == account_id),
+                _ => false,
+            },
+            PermissionCondition::Role(name) => match proposal_kind {
+                ProposalKind::AddMemberToRole { role, .. }
+                | ProposalKind::RemoveMemberFromRole { role, .. } => role == name,
+                _ => false,
+            },
+        }
+    }
+}
+
+/// Permission to execute given action on proposals of given kind,
+/// only if the proposal satisfies all of the `conditions`.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+#[serde(crate = "near_sdk::serde")]
+pub struct ScopedPermission {
+    /// Label of the proposal kind, same as in `<proposal_kind>:<action>` permissions, or "*".
+    pub proposal_kind: String,
+    /// Label of the action, or "*".
+    pub action: String,
+    pub conditions: Vec<PermissionCondition>,
+}
+
+impl ScopedPermission {
+    /// Checks if this permission allows given action on given proposal kind.
+    pub fn allows(&self, proposal_kind: &ProposalKind, action: &Action) -> bool {
+        (self.proposal_kind == "*" || self.proposal_kind == proposal_kind.to_policy_label())
+            && (self.action == "*" || self.action == action.to_policy_label())
+            && self
+                .conditions
+                .iter()
+                .all(|condition| condition.matches(proposal_kind))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryInto;
+
+    use near_sdk::json_types::ValidAccountId;
+
+    use crate::polls::{PollOption, PollTally};
+    use crate::types::BASE_TOKEN;
+
+    use super::*;
+
+    fn transfer(token_id: &str, receiver_id: &str, amount: u128) -> ProposalKind {
+        ProposalKind::Transfer {
+            token_id: token_id.to_string(),
+            receiver_id: receiver_id.to_string().try_into().unwrap(),
+            amount: U128(amount),
+            msg: None,
+        }
+    }
+
+    #[test]
+    fn test_scoped_permission() {
+        let permission = ScopedPermission {
+            proposal_kind: "transfer".to_string(),
+            action: "AddProposal".to_string(),
+            conditions: vec![
+                PermissionCondition::TokenId("token.near".to_string()),
+                PermissionCondition::MaxAmount(U128(100)),
+            ],
+        };
+        assert!(permission.allows(&transfer("token.near", "a.near", 100), &Action::AddProposal));
+        assert!(!permission.allows(&transfer("token.near", "a.near", 100), &Action::VoteApprove));
+        assert!(!permission.allows(&transfer("token.near", "a.near", 101), &Action::AddProposal));
+        assert!(!permission.allows(&transfer(BASE_TOKEN, "a.near", 100), &Action::AddProposal));
+
+        let permission = ScopedPermission {
+            proposal_kind: "*".to_string(),
+            action: "*".to_string(),
+            conditions: vec![PermissionCondition::ReceiverId("a.near".to_string())],
+        };
+        assert!(permission.allows(&transfer(BASE_TOKEN, "a.near", 1000), &Action::AddProposal));
+        assert!(!permission.allows(&transfer(BASE_TOKEN, "b.near", 1000), &Action::AddProposal));
+        let receiver_id: ValidAccountId = "a.near".to_string().try_into().unwrap();
+        assert!(permission.allows(
+            &ProposalKind::FunctionCall {
+                receiver_id,
+                actions: vec![],
+            },
+            &Action::AddProposal
+        ));
+        // Kinds without receiver don't match.
+        assert!(!permission.allows(&ProposalKind::Vote, &Action::AddProposal));
+        let action_poll = |receivers: Vec<&str>| ProposalKind::ActionPoll {
+            options: receivers
+                .into_iter()
+                .map(|receiver_id| PollOption {
+                    label: receiver_id.to_string(),
+                    kind: transfer(BASE_TOKEN, receiver_id, 1),
+                })
+                .collect(),
+            tally: PollTally::Plurality,
+        };
+        assert!(permission.allows(&action_poll(vec!["a.near", "a.near"]), &Action::AddProposal));
+        assert!(!permission.allows(&action_poll(vec!["a.near", "b.near"]), &Action::AddProposal));
+    }
+}
@@ -1,13 +1,19 @@
 use std::cmp::min;
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::json_types::{WrappedDuration, U128};
+use near_sdk::json_types::{WrappedDuration, WrappedTimestamp, U128};
 use near_sdk::serde::{Deserialize, Serialize};
-use near_sdk::{env, AccountId, Balance};
+use near_sdk::{env, AccountId, Balance, Duration, Timestamp};
 
+use crate::permissions::ScopedPermission;
 use crate::proposals::{Proposal, ProposalKind, ProposalStatus, Vote};
-use crate::types::Action;
+use crate::types::{Action, BASE_TOKEN};
+
+/// Longest period or delay the policy can set, 100 years in nanoseconds.
+/// Keeps timestamps computed from them from overflowing.
+pub const MAX_DURATION: Duration = 100 * 365 * 24 * 60 * 60 * 1_000_000_000;
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
@@ -19,6 +25,28 @@ pub enum RoleKind {
     Member(Balance),
     /// Set of accounts.
     Group(HashSet<AccountId>),
+    /// Set of accounts, each member until given time or forever if it's None.
+    /// Expired members are ignored.
+    TermGroup(HashMap<AccountId, Option<WrappedTimestamp>>),
+    /// Set of accounts stored in the contract outside of the policy.
+    /// Members are only changed with `AddMemberToRole` and `RemoveMemberFromRole` proposals.
+    LargeGroup,
+    /// Holders of at least `min_balance` of the fungible token (NEP-141), checked via `ft_balance_of`.
+    TokenHolder {
+        token_id: AccountId,
+        min_balance: U128,
+    },
+    /// Owners of at least `min_tokens` of the non-fungible token contract (NEP-171),
+    /// checked via `nft_supply_for_owner`.
+    NftHolder {
+        contract_id: AccountId,
+        min_tokens: U128,
+    },
+}
+
+/// Returns true if membership with given expiry is still active.
+fn is_term_active(expires_at: &Option<WrappedTimestamp>) -> bool {
+    expires_at.map_or(true, |expires_at| env::block_timestamp() < expires_at.0)
 }
 
 impl RoleKind {
@@ -28,23 +56,56 @@ impl RoleKind {
             RoleKind::Everyone => true,
             RoleKind::Member(amount) => user.amount >= *amount,
             RoleKind::Group(accounts) => accounts.contains(&user.account_id),
+            RoleKind::TermGroup(accounts) => {
+                accounts.get(&user.account_id).map_or(false, is_term_active)
+            }
+            // Membership is looked up by the name of the role in `Policy::get_user_roles`.
+            RoleKind::LargeGroup => false,
+            // Holding is verified by the view call to the token contract before the action.
+            RoleKind::TokenHolder { .. } | RoleKind::NftHolder { .. } => false,
         }
     }
 
     /// Returns the number of people in the this role or None if not supported role kind.
+    /// Size of `LargeGroup` is kept by the contract, see `Policy::get_role_size`.
     pub fn get_role_size(&self) -> Option<usize> {
         match self {
             RoleKind::Group(accounts) => Some(accounts.len()),
+            RoleKind::TermGroup(accounts) => Some(
+                accounts
+                    .values()
+                    .filter(|expires_at| is_term_active(expires_at))
+                    .count(),
+            ),
             _ => None,
         }
     }
 
-    pub fn add_member_to_group(&mut self, member_id: &AccountId) -> Result<(), ()> {
+    /// Adds member to the group. If `expires_at` is given, group becomes a `TermGroup`,
+    /// where existing members stay without expiry.
+    pub fn add_member_to_group(
+        &mut self,
+        member_id: &AccountId,
+        expires_at: Option<Timestamp>,
+    ) -> Result<(), ()> {
         match self {
-            RoleKind::Group(accounts) => {
+            RoleKind::Group(accounts) if expires_at.is_none() => {
                 accounts.insert(member_id.clone());
                 Ok(())
             }
+            RoleKind::Group(accounts) => {
+                let mut members: HashMap<AccountId, Option<WrappedTimestamp>> = accounts
+                    .drain()
+                    .map(|account_id| (account_id, None))
+                    .collect();
+                members.insert(member_id.clone(), expires_at.map(WrappedTimestamp::from));
+                *self = RoleKind::TermGroup(members);
+                Ok(())
+            }
+            RoleKind::TermGroup(accounts) => {
+                accounts.insert(member_id.clone(), expires_at.map(WrappedTimestamp::from));
+                Ok(())
+            }
             _ => Err(()),
         }
     }
@@ -55,6 +116,10 @@ impl RoleKind {
                 accounts.remove(member_id);
                 Ok(())
             }
+            RoleKind::TermGroup(accounts) => {
+                accounts.remove(member_id);
+                Ok(())
+            }
             _ => Err(()),
         }
     }
@@ -71,13 +136,78 @@ pub struct RolePermission {
     /// Set of actions on which proposals that this role is allowed to execute.
     /// <proposal_kind>:<action>
     pub permissions: HashSet<String>,
+    /// Permissions limited to proposals satisfying given conditions on their payload.
+    #[serde(default)]
+    pub scoped_permissions: Vec<ScopedPermission>,
     /// For each proposal kind, defines voting policy.
     pub vote_policy: HashMap<String, VotePolicy>,
+    /// For each token, amount members of this role can transfer via `spend` without a proposal.
+    /// Token is "" for $NEAR or a valid account id.
+    #[serde(default)]
+    pub allowances: HashMap<String, SpendingAllowance>,
+    /// Name of the role this role inherits `permissions` and `vote_policy` entries from.
+    /// Own vote policy entries take precedence over inherited ones. Members are not inherited.
+    #[serde(default)]
+    pub parent: Option<String>,
+}
+
+/// Max amount that can be spent within a rolling window of time.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+#[serde(crate = "near_sdk::serde")]
+pub struct SpendingAllowance {
+    /// Max amount spent by all members of the role together within the window.
+    pub amount: U128,
+    /// Duration of the window.
+    pub window: WrappedDuration,
+}
+
+impl RolePermission {
+    /// Checks if this role's permissions allow given action on given proposal kind.
+    pub fn allows(&self, proposal_kind: &ProposalKind, action: &Action) -> bool {
+        self.permissions.contains(&format!(
+            "{}:{}",
+            proposal_kind.to_policy_label(),
+            action.to_policy_label()
+        )) || self
+            .permissions
+            .contains(&format!("{}:*", proposal_kind.to_policy_label()))
+            || self
+                .permissions
+                .contains(&format!("*:{}", action.to_policy_label()))
+            || self.permissions.contains("*:*")
+            || self
+                .scoped_permissions
+                .iter()
+                .any(|permission| permission.allows(proposal_kind, action))
+    }
+
+    /// Checks if this role's permissions allow voting on any proposal kind.
+    pub fn can_vote(&self) -> bool {
+        let is_vote = |action: &str| {
+            matches!(
+                action,
+                "*" | "VoteApprove" | "VoteReject" | "VoteRemove" | "VotePoll"
+            )
+        };
+        self.permissions
+            .iter()
+            .any(|permission| permission.split(':').nth(1).map_or(false, is_vote))
+            || self
+                .scoped_permissions
+                .iter()
+                .any(|permission| is_vote(&permission.action))
+    }
 }
 
+#[derive(Clone)]
 pub struct UserInfo {
     pub account_id: AccountId,
     pub amount: Balance,
+    /// Names of the large group roles this user is member of.
+    pub large_groups: HashSet<String>,
+    /// Names of the token gated roles, holding for which was verified for the current action, with the holding.
+    pub holder_roles: HashMap<String, Balance>,
 }
 
 /// Direct weight or ratio to total weight, used for the voting policy.
@@ -91,6 +221,11 @@ pub enum WeightOrRatio {
 }
 
 impl WeightOrRatio {
+    /// Returns true if this is a ratio with zero denominator.
+    pub fn has_zero_denominator(&self) -> bool {
+        matches!(self, WeightOrRatio::Ratio(_, 0))
+    }
+
     /// Convert weight or ratio to specific weight given total weight.
     pub fn to_weight(&self, total_weight: Balance) -> Balance {
         match self {
@@ -104,14 +239,22 @@ impl WeightOrRatio {
 }
 
 /// How the voting policy votes get weigthed.
-#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
-#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
 #[serde(crate = "near_sdk::serde")]
 pub enum WeightKind {
     /// Using token amounts and total delegated at the moment.
     TokenWeight,
     /// Weight of the group role. Roles that don't have scoped group are not supported.
     RoleWeight,
+    /// Using integer square root of token amounts and sum of square roots of all delegations.
+    QuadraticTokenWeight,
+    /// Using token amounts averaged over given period before the proposal submission.
+    /// Delegation gets full weight only after being held for the whole period.
+    ConvictionWeight(WrappedDuration),
+    /// Using holding of the token gated role, verified at the time of the vote. Only for `TokenHolder`
+    /// and `NftHolder` roles. Total holding is unknown, so `quorum` is the weight required to decide.
+    HolderWeight,
 }
 
 /// Defines configuration of the vote.
@@ -129,6 +272,18 @@ pub struct VotePolicy {
     pub quorum: U128,
     /// How many votes to pass this vote.
     pub threshold: WeightOrRatio,
+    /// Thresholds for payouts of `transfer`, `bounty_done`, `stream` and `recurring_transfer` proposals,
+    /// per token ("" for $NEAR).
+    /// Tiers must be sorted by `max_amount`. Payouts above all tiers of the token use `threshold`.
+    #[serde(default)]
+    pub amount_thresholds: HashMap<String, Vec<AmountThreshold>>,
+    /// If set, proposal that reached lower threshold is approved after the grace period,
+    /// unless anyone votes to reject it.
+    #[serde(default)]
+    pub optimistic: Option<OptimisticApproval>,
+    /// If set, proposal is approved when its voting period ends, unless reject votes reach this threshold.
+    #[serde(default)]
+    pub veto_threshold: Option<WeightOrRatio>,
 }
 
 impl Default for VotePolicy {
@@ -137,10 +292,81 @@ impl Default for VotePolicy {
             weight_kind: WeightKind::RoleWeight,
             quorum: U128(0),
             threshold: WeightOrRatio::Ratio(1, 2),
+            amount_thresholds: HashMap::default(),
+            optimistic: None,
+            veto_threshold: None,
         }
     }
 }
 
+impl VotePolicy {
+    /// Returns all thresholds of this vote policy.
+    fn thresholds(&self) -> impl Iterator<Item = &WeightOrRatio> {
+        std::iter::once(&self.threshold)
+            .chain(
+                self.amount_thresholds
+                    .values()
+                    .flatten()
+                    .map(|tier| &tier.threshold),
+            )
+            .chain(
+                self.optimistic
+                    .iter()
+                    .map(|optimistic| &optimistic.threshold),
+            )
+            .chain(self.veto_threshold.iter())
+    }
+
+    /// Returns true if any of the thresholds is a ratio with zero denominator.
+    pub fn has_zero_denominator(&self) -> bool {
+        self.thresholds().any(WeightOrRatio::has_zero_denominator)
+    }
+
+    /// Returns true if tiers of each token have strictly increasing `max_amount`.
+    pub fn has_sorted_amount_thresholds(&self) -> bool {
+        self.amount_thresholds.values().all(|tiers| {
+            tiers
+                .windows(2)
+                .all(|pair| pair[0].max_amount.0 < pair[1].max_amount.0)
+        })
+    }
+
+    /// Returns threshold for the proposal paying out given amount of given token, if any.
+    pub fn threshold_for(&self, payout: Option<&(AccountId, Balance)>) -> &WeightOrRatio {
+        payout
+            .and_then(|(token_id, amount)| {
+                self.amount_thresholds
+                    .get(token_id)?
+                    .iter()
+                    .find(|tier| *amount <= tier.max_amount.0)
+            })
+            .map(|tier| &tier.threshold)
+            .unwrap_or(&self.threshold)
+    }
+}
+
+/// Lower threshold to approve uncontested proposals after the grace period.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+#[serde(crate = "near_sdk::serde")]
+pub struct OptimisticApproval {
+    /// How many votes to start the grace period.
+    pub threshold: WeightOrRatio,
+    /// How long the proposal must stay without reject votes to be approved.
+    pub grace_period: WrappedDuration,
+}
+
+/// Threshold for payouts up to given amount.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+#[serde(crate = "near_sdk::serde")]
+pub struct AmountThreshold {
+    /// Max amount of the payout in this tier.
+    pub max_amount: U128,
+    /// How many votes to pass payout in this tier.
+    pub threshold: WeightOrRatio,
+}
+
 /// Defines voting / decision making policy of this DAO.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
@@ -152,12 +378,178 @@ pub struct Policy {
     pub default_vote_policy: VotePolicy,
     /// Proposal bond.
     pub proposal_bond: U128,
+    /// Token the proposal bonds are paid in. Can be "" for $NEAR or a valid account id.
+    /// Bonds in tokens are paid by adding proposal via `ft_transfer_call` to this contract.
+    #[serde(default)]
+    pub proposal_bond_token: AccountId,
     /// Expiration period for proposals.
     pub proposal_period: WrappedDuration,
+    /// Proposal bond per proposal kind. Used instead of `proposal_bond` for listed kinds.
+    #[serde(default)]
+    pub proposal_bonds: HashMap<String, U128>,
+    /// Expiration period per proposal kind. Used instead of `proposal_period` for listed kinds.
+    #[serde(default)]
+    pub proposal_periods: HashMap<String, WrappedDuration>,
     /// Bond for claiming a bounty.
     pub bounty_bond: U128,
     /// Period in which giving up on bounty is not punished.
     pub bounty_forgiveness_period: WrappedDuration,
+    /// Delay between approval and execution per proposal kind.
+    /// Approved proposals of listed kinds are queued, other kinds are executed right away.
+    #[serde(default)]
+    pub execution_delay: HashMap<String, WrappedDuration>,
+    /// Hub or another DAO, where proposals can be moved with `MoveToHub` action.
+    #[serde(default)]
+    pub hub_id: Option<AccountId>,
+    /// Number of members per large group role, loaded from the contract. Not stored with the policy.
+    #[borsh_skip]
+    #[serde(skip)]
+    pub large_group_sizes: HashMap<String, u64>,
+}
+
+/// Reason why the policy can't be used.
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub enum PolicyError {
+    /// Several roles have given name.
+    DuplicateRole(String),
+    /// Given role votes with `RoleWeight` on given proposal kind, but the size of the role is unknown.
+    /// Proposal kind is "*" for the default vote policy.
+    UnsupportedRoleWeight { role: String, proposal_kind: String },
+    /// Vote policy of given role and proposal kind has ratio with zero denominator.
+    /// Proposal kind is "*" for the default vote policy.
+    ZeroDenominator { role: String, proposal_kind: String },
+    /// Amount thresholds of given role and proposal kind aren't sorted by increasing `max_amount`.
+    /// Proposal kind is "*" for the default vote policy.
+    UnsortedAmountThresholds { role: String, proposal_kind: String },
+    /// No one is able to vote on proposals.
+    NoVoters,
+    /// Parent of given role doesn't exist.
+    UnknownParent { role: String, parent: String },
+    /// Parents of given role form a cycle.
+    RoleCycle(String),
+    /// Given duration is longer than `MAX_DURATION`, e.g. "execution_delay:transfer".
+    DurationTooLong(String),
+    /// Given role votes with `HolderWeight` on given proposal kind, but isn't token gated,
+    /// has zero quorum, or uses optimistic approval or veto threshold, which require total weight.
+    /// Proposal kind is "*" for the default vote policy.
+    UnsupportedHolderWeight { role: String, proposal_kind: String },
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PolicyError::DuplicateRole(role) => write!(f, "ERR_DUPLICATE_ROLE:{}", role),
+            PolicyError::UnsupportedRoleWeight {
+                role,
+                proposal_kind,
+            } => write!(f, "ERR_UNSUPPORTED_ROLE:{}:{}", role, proposal_kind),
+            PolicyError::ZeroDenominator {
+                role,
+                proposal_kind,
+            } => write!(f, "ERR_ZERO_DENOMINATOR:{}:{}", role, proposal_kind),
+            PolicyError::UnsortedAmountThresholds {
+                role,
+                proposal_kind,
+            } => write!(
+                f,
+                "ERR_UNSORTED_AMOUNT_THRESHOLDS:{}:{}",
+                role, proposal_kind
+            ),
+            PolicyError::NoVoters => write!(f, "ERR_NO_VOTERS"),
+            PolicyError::UnknownParent { role, parent } => {
+                write!(f, "ERR_UNKNOWN_PARENT_ROLE:{}:{}", role, parent)
+            }
+            PolicyError::RoleCycle(role) => write!(f, "ERR_ROLE_CYCLE:{}", role),
+            PolicyError::DurationTooLong(name) => write!(f, "ERR_DURATION_TOO_LONG:{}", name),
+            PolicyError::UnsupportedHolderWeight {
+                role,
+                proposal_kind,
+            } => write!(
+                f,
+                "ERR_UNSUPPORTED_HOLDER_WEIGHT:{}:{}",
+                role, proposal_kind
+            ),
+        }
+    }
+}
+
+/// Vote policy in the layout stored before `amount_thresholds`, `optimistic` and `veto_threshold`.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct VotePolicyV1 {
+    pub weight_kind: WeightKind,
+    pub quorum: U128,
+    pub threshold: WeightOrRatio,
+}
+
+impl From<VotePolicyV1> for VotePolicy {
+    fn from(vote_policy: VotePolicyV1) -> Self {
+        VotePolicy {
+            weight_kind: vote_policy.weight_kind,
+            quorum: vote_policy.quorum,
+            threshold: vote_policy.threshold,
+            ..VotePolicy::default()
+        }
+    }
+}
+
+/// Role in the layout stored before `scoped_permissions`, `allowances` and `parent`.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct RolePermissionV1 {
+    pub name: String,
+    pub kind: RoleKind,
+    pub permissions: HashSet<String>,
+    pub vote_policy: HashMap<String, VotePolicyV1>,
+}
+
+impl From<RolePermissionV1> for RolePermission {
+    fn from(role: RolePermissionV1) -> Self {
+        RolePermission {
+            name: role.name,
+            kind: role.kind,
+            permissions: role.permissions,
+            scoped_permissions: vec![],
+            vote_policy: role
+                .vote_policy
+                .into_iter()
+                .map(|(proposal_kind, vote_policy)| (proposal_kind, vote_policy.into()))
+                .collect(),
+            allowances: HashMap::default(),
+            parent: None,
+        }
+    }
+}
+
+/// Policy in the layout stored before per proposal kind bonds and periods.
+#[derive(BorshSerialize, BorshDeserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+pub struct PolicyV1 {
+    pub roles: Vec<RolePermissionV1>,
+    pub default_vote_policy: VotePolicyV1,
+    pub proposal_bond: U128,
+    pub proposal_period: WrappedDuration,
+    pub bounty_bond: U128,
+    pub bounty_forgiveness_period: WrappedDuration,
+}
+
+impl From<PolicyV1> for Policy {
+    fn from(policy: PolicyV1) -> Self {
+        Policy {
+            roles: policy.roles.into_iter().map(RolePermission::from).collect(),
+            default_vote_policy: policy.default_vote_policy.into(),
+            proposal_bond: policy.proposal_bond,
+            proposal_bond_token: BASE_TOKEN.to_string(),
+            proposal_period: policy.proposal_period,
+            proposal_bonds: HashMap::default(),
+            proposal_periods: HashMap::default(),
+            bounty_bond: policy.bounty_bond,
+            bounty_forgiveness_period: policy.bounty_forgiveness_period,
+            execution_delay: HashMap::default(),
+            hub_id: None,
+            large_group_sizes: HashMap::default(),
+        }
+    }
 }
 
 /// Versioned policy.
@@ -167,6 +559,9 @@ pub struct Policy {
 pub enum VersionedPolicy {
     /// Default policy with given accounts as council.
     Default(Vec<AccountId>),
+    /// Policy stored by the previous version of the contract. Upgraded when it's read.
+    #[serde(skip)]
+    V1(PolicyV1),
     Current(Policy),
 }
 
@@ -183,7 +578,10 @@ fn default_policy(council: Vec<AccountId>) -> Policy {
                 name: "all".to_string(),
                 kind: RoleKind::Everyone,
                 permissions: vec!["*:AddProposal".to_string()].into_iter().collect(),
+                scoped_permissions: vec![],
                 vote_policy: HashMap::default(),
+                allowances: HashMap::default(),
+                parent: None,
             },
             RolePermission {
                 name: "council".to_string(),
@@ -195,17 +593,30 @@ fn default_policy(council: Vec<AccountId>) -> Policy {
                     "*:VoteReject".to_string(),
                     "*:VoteRemove".to_string(),
                     "*:Finalize".to_string(),
+                    "*:Retry".to_string(),
+                    "*:ChangeVote".to_string(),
+                    "*:Unvote".to_string(),
+                    "*:VotePoll".to_string(),
                 ]
                 .into_iter()
                 .collect(),
+                scoped_permissions: vec![],
                 vote_policy: HashMap::default(),
+                allowances: HashMap::default(),
+                parent: None,
             },
         ],
         default_vote_policy: VotePolicy::default(),
         proposal_bond: U128(10u128.pow(24)),
+        proposal_bond_token: BASE_TOKEN.to_string(),
         proposal_period: WrappedDuration::from(1_000_000_000 * 60 * 60 * 24 * 7),
+        proposal_bonds: HashMap::default(),
+        proposal_periods: HashMap::default(),
         bounty_bond: U128(10u128.pow(24)),
         bounty_forgiveness_period: WrappedDuration::from(1_000_000_000 * 60 * 60 * 24),
+        execution_delay: HashMap::default(),
+        hub_id: None,
+        large_group_sizes: HashMap::default(),
     }
 }
 
@@ -216,6 +627,7 @@ impl VersionedPolicy {
             VersionedPolicy::Default(accounts) => {
                 VersionedPolicy::Current(default_policy(accounts))
             }
+            VersionedPolicy::V1(policy) => VersionedPolicy::Current(policy.into()),
             VersionedPolicy::Current(policy) => VersionedPolicy::Current(policy),
         }
     }
@@ -223,6 +635,7 @@ impl VersionedPolicy {
     /// Return recent version of policy.
     pub fn to_policy(self) -> Policy {
         match self {
+            VersionedPolicy::V1(policy) => policy.into(),
             VersionedPolicy::Current(policy) => policy,
             _ => unimplemented!(),
         }
@@ -237,14 +650,149 @@ impl VersionedPolicy {
 }
 
 impl Policy {
-    ///
+    /// Checks that this policy can be used by the DAO without failing later.
+    pub fn validate(&self) -> Result<(), PolicyError> {
+        if self.proposal_period.0 > MAX_DURATION {
+            return Err(PolicyError::DurationTooLong("proposal_period".to_string()));
+        }
+        if self.bounty_forgiveness_period.0 > MAX_DURATION {
+            return Err(PolicyError::DurationTooLong(
+                "bounty_forgiveness_period".to_string(),
+            ));
+        }
+        for (proposal_kind, period) in self.proposal_periods.iter() {
+            if period.0 > MAX_DURATION {
+                return Err(PolicyError::DurationTooLong(format!(
+                    "proposal_periods:{}",
+                    proposal_kind
+                )));
+            }
+        }
+        for (proposal_kind, delay) in self.execution_delay.iter() {
+            if delay.0 > MAX_DURATION {
+                return Err(PolicyError::DurationTooLong(format!(
+                    "execution_delay:{}",
+                    proposal_kind
+                )));
+            }
+        }
+        let mut names = HashSet::new();
+        for role in self.roles.iter() {
+            if !names.insert(&role.name) {
+                return Err(PolicyError::DuplicateRole(role.name.clone()));
+            }
+        }
+        for role in self.roles.iter() {
+            let mut ancestors = HashSet::new();
+            let mut parent = role.parent.as_ref();
+            while let Some(name) = parent {
+                if name == &role.name || !ancestors.insert(name) {
+                    return Err(PolicyError::RoleCycle(role.name.clone()));
+                }
+                parent = self
+                    .internal_get_role(name)
+                    .ok_or_else(|| PolicyError::UnknownParent {
+                        role: role.name.clone(),
+                        parent: name.clone(),
+                    })?
+                    .parent
+                    .as_ref();
+            }
+        }
+        for role in self.roles.iter() {
+            let ancestry = self.internal_role_ancestry(role);
+            let can_vote = ancestry.iter().any(|role| role.can_vote());
+            let vote_policies = ancestry
+                .iter()
+                .flat_map(|role| role.vote_policy.iter())
+                .map(|(proposal_kind, vote_policy)| (proposal_kind.as_str(), vote_policy))
+                .chain(std::iter::once(("*", &self.default_vote_policy)));
+            for (proposal_kind, vote_policy) in vote_policies {
+                if vote_policy.has_zero_denominator() {
+                    return Err(PolicyError::ZeroDenominator {
+                        role: role.name.clone(),
+                        proposal_kind: proposal_kind.to_string(),
+                    });
+                }
+                if !vote_policy.has_sorted_amount_thresholds() {
+                    return Err(PolicyError::UnsortedAmountThresholds {
+                        role: role.name.clone(),
+                        proposal_kind: proposal_kind.to_string(),
+                    });
+                }
+                if let WeightKind::ConvictionWeight(period) = vote_policy.weight_kind {
+                    if period.0 > MAX_DURATION {
+                        return Err(PolicyError::DurationTooLong(format!(
+                            "conviction_period:{}:{}",
+                            role.name, proposal_kind
+                        )));
+                    }
+                }
+                if let Some(optimistic) = &vote_policy.optimistic {
+                    if optimistic.grace_period.0 > MAX_DURATION {
+                        return Err(PolicyError::DurationTooLong(format!(
+                            "grace_period:{}:{}",
+                            role.name, proposal_kind
+                        )));
+                    }
+                }
+                // Token holders aren't counted either: tokens can move to another account to vote again.
+                if can_vote
+                    && matches!(vote_policy.weight_kind, WeightKind::RoleWeight)
+                    && self.get_role_size(role).is_none()
+                {
+                    return Err(PolicyError::UnsupportedRoleWeight {
+                        role: role.name.clone(),
+                        proposal_kind: proposal_kind.to_string(),
+                    });
+                }
+                if can_vote
+                    && matches!(vote_policy.weight_kind, WeightKind::HolderWeight)
+                    && (!matches!(
+                        role.kind,
+                        RoleKind::TokenHolder { .. } | RoleKind::NftHolder { .. }
+                    ) || vote_policy.quorum.0 == 0
+                        || vote_policy.optimistic.is_some()
+                        || vote_policy.veto_threshold.is_some())
+                {
+                    return Err(PolicyError::UnsupportedHolderWeight {
+                        role: role.name.clone(),
+                        proposal_kind: proposal_kind.to_string(),
+                    });
+                }
+            }
+        }
+        if !self.roles.iter().any(|role| {
+            self.internal_role_ancestry(role)
+                .iter()
+                .any(|role| role.can_vote())
+                && self.get_role_size(role) != Some(0)
+        }) {
+            return Err(PolicyError::NoVoters);
+        }
+        Ok(())
+    }
+
+    /// Fails with the error of `validate` if this policy is invalid.
+    pub fn assert_valid(&self) {
+        if let Err(err) = self.validate() {
+            env::panic(err.to_string().as_bytes());
+        }
+    }
+
+    /// Adds member to given role, until `expires_at` if given.
     /// Doesn't fail, because will be used on the finalization of the proposal.
-    pub fn add_member_to_role(&mut self, role: &String, member_id: &AccountId) {
+    pub fn add_member_to_role(
+        &mut self,
+        role: &String,
+        member_id: &AccountId,
+        expires_at: Option<Timestamp>,
+    ) {
         for i in 0..self.roles.len() {
             if &self.roles[i].name == role {
                 self.roles[i]
                     .kind
-                    .add_member_to_group(member_id)
+                    .add_member_to_group(member_id, expires_at)
                     .unwrap_or_else(|()| {
                         env::log(&format!("ERR_ROLE_WRONG_KIND:{}", role).into_bytes());
                     });
@@ -269,15 +817,49 @@ impl Policy {
         env::log(&format!("ERR_ROLE_NOT_FOUND:{}", role).into_bytes());
     }
 
-    /// Returns set of roles that this user is memeber of permissions for given user across all the roles it's member of.
-    fn get_user_roles(&self, user: UserInfo) -> HashMap<String, &HashSet<String>> {
-        let mut roles = HashMap::default();
-        for role in self.roles.iter() {
-            if role.kind.match_user(&user) {
-                roles.insert(role.name.clone(), &role.permissions);
-            }
+    /// Returns set of roles that this user is memeber of.
+    pub(crate) fn get_user_roles(&self, user: UserInfo) -> Vec<&RolePermission> {
+        self.roles
+            .iter()
+            .filter(|role| match role.kind {
+                RoleKind::LargeGroup => user.large_groups.contains(&role.name),
+                RoleKind::TokenHolder { .. } | RoleKind::NftHolder { .. } => {
+                    user.holder_roles.contains_key(&role.name)
+                }
+                _ => role.kind.match_user(&user),
+            })
+            .collect()
+    }
+
+    /// Returns true if given role is a large group.
+    pub fn is_large_group(&self, role: &String) -> bool {
+        matches!(
+            self.internal_get_role(role).map(|role| &role.kind),
+            Some(RoleKind::LargeGroup)
+        )
+    }
+
+    /// Sets number of members of each large group to the one returned by `size` for its name.
+    pub fn set_large_group_sizes(&mut self, size: impl Fn(&String) -> u64) {
+        self.large_group_sizes = self
+            .roles
+            .iter()
+            .filter(|role| matches!(role.kind, RoleKind::LargeGroup))
+            .map(|role| (role.name.clone(), size(&role.name)))
+            .collect();
+    }
+
+    /// Returns the number of people in given role or None if not supported role kind.
+    pub fn get_role_size(&self, role: &RolePermission) -> Option<usize> {
+        match role.kind {
+            RoleKind::LargeGroup => Some(
+                self.large_group_sizes
+                    .get(&role.name)
+                    .copied()
+                    .unwrap_or_default() as usize,
+            ),
+            _ => role.kind.get_role_size(),
         }
-        roles
     }
 
     /// Can given user execute given action on this proposal.
@@ -288,42 +870,87 @@ impl Policy {
         proposal_kind: &ProposalKind,
         action: &Action,
     ) -> (Vec<String>, bool) {
-        let roles = self.get_user_roles(user);
-        let mut allowed = false;
-        let allowed_roles = roles
+        let allowed_roles: Vec<String> = self
+            .get_user_roles(user)
             .into_iter()
-            .filter_map(|(role, permissions)| {
-                let allowed_role = permissions.contains(&format!(
-                    "{}:{}",
-                    proposal_kind.to_policy_label(),
-                    action.to_policy_label()
-                )) || permissions
-                    .contains(&format!("{}:*", proposal_kind.to_policy_label()))
-                    || permissions.contains(&format!("*:{}", action.to_policy_label()))
-                    || permissions.contains("*:*");
-                allowed = allowed || allowed_role;
-                if allowed_role {
-                    Some(role)
-                } else {
-                    None
-                }
-            })
+            .filter(|role| self.internal_role_allows(role, proposal_kind, action))
+            .map(|role| role.name.clone())
             .collect();
+        let allowed = !allowed_roles.is_empty();
         (allowed_roles, allowed)
     }
 
-    /// Returns if given proposal kind is token weighted.
-    pub fn is_token_weighted(&self, role: &String, proposal_kind_label: &String) -> bool {
+    /// Returns if given user can add proposal of given kind.
+    /// Winning option of the action poll is executed as is, so each option must be allowed too.
+    pub fn can_add_proposal(&self, user: &UserInfo, proposal_kind: &ProposalKind) -> bool {
+        let options = match proposal_kind {
+            ProposalKind::ActionPoll { options, .. } => options.as_slice(),
+            _ => &[],
+        };
+        std::iter::once(proposal_kind)
+            .chain(options.iter().map(|option| &option.kind))
+            .all(|kind| {
+                self.can_execute_action(user.clone(), kind, &Action::AddProposal)
+                    .1
+            })
+    }
+
+    /// Returns how votes of given role are weighted for given proposal kind.
+    pub fn get_weight_kind(&self, role: &String, proposal_kind_label: &String) -> &WeightKind {
         let role_info = self.internal_get_role(role).expect("ERR_ROLE_NOT_FOUND");
-        match role_info
-            .vote_policy
-            .get(proposal_kind_label)
+        &self
+            .internal_role_ancestry(role_info)
+            .into_iter()
+            .find_map(|role| role.vote_policy.get(proposal_kind_label))
             .unwrap_or(&self.default_vote_policy)
             .weight_kind
-        {
-            WeightKind::TokenWeight => true,
-            _ => false,
-        }
+    }
+
+    /// Returns proposal bond for given proposal kind.
+    pub fn get_proposal_bond(&self, proposal_kind: &ProposalKind) -> Balance {
+        self.proposal_bonds
+            .get(proposal_kind.to_policy_label())
+            .unwrap_or(&self.proposal_bond)
+            .0
+    }
+
+    /// Returns expiration period for given proposal kind.
+    pub fn get_proposal_period(&self, proposal_kind: &ProposalKind) -> Duration {
+        self.proposal_periods
+            .get(proposal_kind.to_policy_label())
+            .unwrap_or(&self.proposal_period)
+            .0
+    }
+
+    /// Returns the longest period of `ConvictionWeight` used in this policy, 0 if none.
+    pub fn max_conviction_period(&self) -> Duration {
+        self.roles
+            .iter()
+            .flat_map(|role| role.vote_policy.values())
+            .chain(std::iter::once(&self.default_vote_policy))
+            .filter_map(|vote_policy| match vote_policy.weight_kind {
+                WeightKind::ConvictionWeight(period) => Some(period.0),
+                _ => None,
+            })
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Returns delay between approval and execution for given proposal kind.
+    fn internal_execution_delay(&self, proposal_kind: &ProposalKind) -> Duration {
+        self.execution_delay
+            .get(proposal_kind.to_policy_label())
+            .map(|delay| delay.0)
+            .unwrap_or_default()
+    }
+
+    /// Returns delay between approval and execution for given proposal.
+    /// Action poll is delayed as its winning option, if that's longer.
+    pub fn execution_delay(&self, proposal: &Proposal) -> Duration {
+        std::cmp::max(
+            self.internal_execution_delay(&proposal.kind),
+            self.internal_execution_delay(proposal.executed_kind()),
+        )
     }
 
     fn internal_get_role(&self, name: &String) -> Option<&RolePermission> {
@@ -335,43 +962,348 @@ impl Policy {
         None
     }
 
+    /// Returns given role followed by its parent, parent's parent and so on.
+    /// Stops at the missing parent or at the cycle, if policy wasn't validated.
+    fn internal_role_ancestry<'a>(
+        &'a self,
+        role_info: &'a RolePermission,
+    ) -> Vec<&'a RolePermission> {
+        let mut ancestry = vec![role_info];
+        let mut parent = role_info.parent.as_ref();
+        while let Some(name) = parent {
+            match self.internal_get_role(name) {
+                Some(role) if ancestry.iter().all(|ancestor| ancestor.name != role.name) => {
+                    ancestry.push(role);
+                    parent = role.parent.as_ref();
+                }
+                _ => break,
+            }
+        }
+        ancestry
+    }
+
+    /// Checks if permissions of given role, including inherited ones, allow given action on given proposal kind.
+    fn internal_role_allows(
+        &self,
+        role_info: &RolePermission,
+        proposal_kind: &ProposalKind,
+        action: &Action,
+    ) -> bool {
+        self.internal_role_ancestry(role_info)
+            .iter()
+            .any(|role| role.allows(proposal_kind, action))
+    }
+
+    /// Returns vote policy of given role for given proposal kind.
+    /// Own entry of the role is used first, then the nearest ancestor's one, then the default.
+    fn internal_vote_policy<'a>(
+        &'a self,
+        role_info: &'a RolePermission,
+        proposal_kind: &ProposalKind,
+    ) -> &'a VotePolicy {
+        self.internal_role_ancestry(role_info)
+            .into_iter()
+            .find_map(|role| role.vote_policy.get(proposal_kind.to_policy_label()))
+            .unwrap_or(&self.default_vote_policy)
+    }
+
+    /// Returns total weight of votes given role can cast on given proposal kind.
+    /// `total_supply` returns total weight of delegated tokens for given token weight kind.
+    /// None if the size of the role or the total holding is unknown.
+    fn internal_total_weight(
+        &self,
+        role_info: &RolePermission,
+        proposal_kind: &ProposalKind,
+        total_supply: &dyn Fn(&WeightKind) -> Balance,
+    ) -> Option<Balance> {
+        match &self
+            .internal_vote_policy(role_info, proposal_kind)
+            .weight_kind
+        {
+            WeightKind::RoleWeight => self.get_role_size(role_info).map(|size| size as Balance),
+            WeightKind::HolderWeight => None,
+            weight_kind => Some(total_supply(weight_kind)),
+        }
+    }
+
+    /// Returns weight of votes required from given role to decide on given proposal kind.
+    /// `payout` is the token and amount paid out by the proposal kind, if any.
+    /// It's the quorum for `HolderWeight`, as total holding is unknown.
+    fn internal_threshold(
+        &self,
+        role_info: &RolePermission,
+        proposal_kind: &ProposalKind,
+        total_supply: &dyn Fn(&WeightKind) -> Balance,
+        payout: &dyn Fn(&ProposalKind) -> Option<(AccountId, Balance)>,
+    ) -> Balance {
+        let vote_policy = self.internal_vote_policy(role_info, proposal_kind);
+        if vote_policy.weight_kind == WeightKind::HolderWeight {
+            return vote_policy.quorum.0;
+        }
+        let total_weight = self
+            .internal_total_weight(role_info, proposal_kind, total_supply)
+            .expect("ERR_UNSUPPORTED_ROLE");
+        std::cmp::max(
+            vote_policy.quorum.0,
+            vote_policy
+                .threshold_for(payout(proposal_kind).as_ref())
+                .to_weight(total_weight),
+        )
+    }
+
+    /// Returns if voting period of given proposal is over.
+    pub fn is_proposal_expired(&self, proposal: &Proposal) -> bool {
+        proposal
+            .submission_time
+            .0
+            .saturating_add(self.get_proposal_period(&proposal.kind))
+            < env::block_timestamp()
+    }
+
+    /// Returns if none of the roles that can vote on given proposal can reach the threshold to approve it,
+    /// even if everyone who hasn't voted yet approves, and so do voters who can change their vote.
+    /// Optimistic threshold counts too, as reaching it approves the proposal after the grace period.
+    /// Token weighted roles are bounded by the current total supply of delegated tokens.
+    pub fn is_approval_unreachable(
+        &self,
+        proposal: &Proposal,
+        total_supply: &dyn Fn(&WeightKind) -> Balance,
+        payout: &dyn Fn(&ProposalKind) -> Option<(AccountId, Balance)>,
+    ) -> bool {
+        if proposal.kind.poll().is_some() {
+            // Polls always run until the end of the voting period.
+            return false;
+        }
+        let mut approvers = self
+            .roles
+            .iter()
+            .filter(|role| self.internal_role_allows(role, &proposal.kind, &Action::VoteApprove))
+            .peekable();
+        if approvers.peek().is_none() {
+            // No role can approve it, so there are no votes to decide early, it expires instead.
+            return false;
+        }
+        let can_change_votes = |role: &RolePermission| {
+            self.internal_role_allows(role, &proposal.kind, &Action::ChangeVote(Vote::Approve))
+                || self.internal_role_allows(role, &proposal.kind, &Action::Unvote)
+        };
+        // Optimistic approval requires that no one rejected it.
+        let rejection_final = proposal.vote_counts.iter().any(|(name, vote_counts)| {
+            vote_counts[Vote::Reject as usize] > 0
+                && !self
+                    .internal_get_role(name)
+                    .map_or(false, |role| can_change_votes(role))
+        });
+        approvers.all(|role| {
+            let vote_policy = self.internal_vote_policy(role, &proposal.kind);
+            if vote_policy.veto_threshold.is_some() {
+                // Approved at the end of the period unless vetoed.
+                return false;
+            }
+            match self.internal_total_weight(role, &proposal.kind, total_supply) {
+                Some(total_weight) => {
+                    let vote_counts = proposal
+                        .vote_counts
+                        .get(&role.name)
+                        .cloned()
+                        .unwrap_or_default();
+                    let voted: Balance = vote_counts.iter().sum();
+                    let mut best_approval =
+                        vote_counts[Vote::Approve as usize] + total_weight.saturating_sub(voted);
+                    if can_change_votes(role) {
+                        best_approval +=
+                            vote_counts[Vote::Reject as usize] + vote_counts[Vote::Remove as usize];
+                    }
+                    let mut threshold =
+                        self.internal_threshold(role, &proposal.kind, total_supply, payout);
+                    if let Some(optimistic) =
+                        vote_policy.optimistic.as_ref().filter(|_| !rejection_final)
+                    {
+                        threshold = min(
+                            threshold,
+                            std::cmp::max(
+                                vote_policy.quorum.0,
+                                optimistic.threshold.to_weight(total_weight),
+                            ),
+                        );
+                    }
+                    best_approval < threshold
+                }
+                // Can't tell how many can still vote in this role.
+                None => false,
+            }
+        })
+    }
+
+    /// Returns grace period after which given proposal is approved, if it reached optimistic threshold
+    /// of any role that can approve it and no one voted to reject it.
+    /// If several roles reached it, the shortest grace period is used.
+    pub fn optimistic_grace_period(
+        &self,
+        proposal: &Proposal,
+        total_supply: &dyn Fn(&WeightKind) -> Balance,
+    ) -> Option<Duration> {
+        if proposal
+            .vote_counts
+            .values()
+            .any(|vote_counts| vote_counts[Vote::Reject as usize] > 0)
+        {
+            return None;
+        }
+        self.roles
+            .iter()
+            .filter(|role| self.internal_role_allows(role, &proposal.kind, &Action::VoteApprove))
+            .filter_map(|role| {
+                let vote_policy = self.internal_vote_policy(role, &proposal.kind);
+                let optimistic = vote_policy.optimistic.as_ref()?;
+                let vote_counts = proposal.vote_counts.get(&role.name)?;
+                let total_weight =
+                    self.internal_total_weight(role, &proposal.kind, total_supply)?;
+                let threshold = std::cmp::max(
+                    vote_policy.quorum.0,
+                    optimistic.threshold.to_weight(total_weight),
+                );
+                if vote_counts[Vote::Approve as usize] >= threshold {
+                    Some(optimistic.grace_period.0)
+                } else {
+                    None
+                }
+            })
+            .min()
+    }
+
+    /// Returns status of given proposal once its voting period is over.
+    /// If roles that can approve it use veto threshold, it's approved unless any of them vetoed it.
+    /// Poll is approved if it has a winner. Otherwise it's expired.
+    pub fn expired_proposal_status(
+        &self,
+        proposal: &Proposal,
+        total_supply: &dyn Fn(&WeightKind) -> Balance,
+        payout: &dyn Fn(&ProposalKind) -> Option<(AccountId, Balance)>,
+    ) -> ProposalStatus {
+        match proposal.kind {
+            ProposalKind::Poll { .. } if !proposal.poll_winners().is_empty() => {
+                return ProposalStatus::Approved
+            }
+            ProposalKind::ActionPoll { .. }
+                if self
+                    .action_poll_winner(proposal, total_supply, payout)
+                    .is_some() =>
+            {
+                return ProposalStatus::Approved
+            }
+            ProposalKind::Poll { .. } | ProposalKind::ActionPoll { .. } => {
+                return ProposalStatus::Expired
+            }
+            _ => {}
+        }
+        let mut veto_roles = self
+            .roles
+            .iter()
+            .filter(|role| self.internal_role_allows(role, &proposal.kind, &Action::VoteApprove))
+            .filter_map(|role| {
+                let veto_threshold = self
+                    .internal_vote_policy(role, &proposal.kind)
+                    .veto_threshold
+                    .as_ref()?;
+                Some((role, veto_threshold))
+            })
+            .peekable();
+        if veto_roles.peek().is_none() {
+            return ProposalStatus::Expired;
+        }
+        let vetoed = veto_roles.any(|(role, veto_threshold)| {
+            let total_weight = self
+                .internal_total_weight(role, &proposal.kind, total_supply)
+                .expect("ERR_UNSUPPORTED_ROLE");
+            let rejected = proposal
+                .vote_counts
+                .get(&role.name)
+                .map(|vote_counts| vote_counts[Vote::Reject as usize])
+                .unwrap_or_default();
+            rejected >= veto_threshold.to_weight(total_weight)
+        });
+        if vetoed {
+            ProposalStatus::Rejected
+        } else {
+            ProposalStatus::Approved
+        }
+    }
+
+    /// Returns winning option of given action poll. Only roles where total weight of poll votes
+    /// reached their threshold decide, and they must agree on the winner.
+    /// Winning option is executed, so the role must also be allowed to approve its proposal kind
+    /// with the same weight kind, and the turnout must reach the threshold for that kind.
+    pub fn action_poll_winner(
+        &self,
+        proposal: &Proposal,
+        total_supply: &dyn Fn(&WeightKind) -> Balance,
+        payout: &dyn Fn(&ProposalKind) -> Option<(AccountId, Balance)>,
+    ) -> Option<u64> {
+        let options = match &proposal.kind {
+            ProposalKind::ActionPoll { options, .. } => options,
+            _ => return None,
+        };
+        let poll_winners = proposal.poll_winners();
+        let winners: HashSet<u64> = self
+            .roles
+            .iter()
+            .filter(|role| {
+                self.internal_role_allows(role, &proposal.kind, &Action::VotePoll(vec![]))
+            })
+            .filter_map(|role| {
+                let winner = *poll_winners.get(&role.name)?;
+                let kind = &options[winner as usize].kind;
+                let turnout = proposal.poll_turnout(&role.name);
+                if self.internal_role_allows(role, kind, &Action::VoteApprove)
+                    && self.internal_vote_policy(role, kind).weight_kind
+                        == self.internal_vote_policy(role, &proposal.kind).weight_kind
+                    && turnout
+                        >= self.internal_threshold(role, &proposal.kind, total_supply, payout)
+                    && turnout >= self.internal_threshold(role, kind, total_supply, payout)
+                {
+                    Some(winner)
+                } else {
+                    None
+                }
+            })
+            .collect();
+        if winners.len() == 1 {
+            winners.into_iter().next()
+        } else {
+            None
+        }
+    }
+
     /// Get proposal status for given proposal.
     /// Usually is called after changing it's state.
+    /// `payout` returns the token and amount paid out by given proposal kind, which selects the threshold tier.
     pub fn proposal_status(
         &self,
         proposal: &Proposal,
         roles: Vec<String>,
-        total_supply: Balance,
+        total_supply: &dyn Fn(&WeightKind) -> Balance,
+        payout: &dyn Fn(&ProposalKind) -> Option<(AccountId, Balance)>,
     ) -> ProposalStatus {
-        assert_eq!(
-            proposal.status,
-            ProposalStatus::InProgress,
+        assert!(
+            proposal.status == ProposalStatus::InProgress
+                || proposal.status == ProposalStatus::Delay,
             "ERR_PROPOSAL_NOT_IN_PROGRESS"
         );
-        if proposal.submission_time.0 + self.proposal_period.0 < env::block_timestamp() {
-            // Proposal expired.
-            return ProposalStatus::Expired;
+        if self.is_proposal_expired(proposal) {
+            // Proposal expired, unless it's approved or vetoed at the end of the period.
+            return self.expired_proposal_status(proposal, total_supply, payout);
         };
         for role in roles {
             let role_info = self.internal_get_role(&role).expect("ERR_MISSING_ROLE");
-            let vote_policy = role_info
-                .vote_policy
-                .get(&proposal.kind.to_policy_label().to_string())
-                .unwrap_or(&self.default_vote_policy);
-            let threshold = std::cmp::max(
-                vote_policy.quorum.0,
-                match &vote_policy.weight_kind {
-                    WeightKind::TokenWeight => vote_policy.threshold.to_weight(total_supply),
-                    WeightKind::RoleWeight => vote_policy.threshold.to_weight(
-                        role_info
-                            .kind
-                            .get_role_size()
-                            .expect("ERR_UNSUPPORTED_ROLE") as Balance,
-                    ),
-                },
-            );
+            let threshold =
+                self.internal_threshold(role_info, &proposal.kind, total_supply, payout);
             // Check if there is anything voted above the threshold specified by policy for given role.
-            let vote_counts = proposal.vote_counts.get(&role).expect("ERR_MISSING_ROLE");
+            // Role could have been added after the votes were cast, nothing is counted for it then.
+            let vote_counts = match proposal.vote_counts.get(&role) {
+                Some(vote_counts) => vote_counts,
+                None => continue,
+            };
             if vote_counts[Vote::Approve as usize] >= threshold {
                 return ProposalStatus::Approved;
             } else if vote_counts[Vote::Reject as usize] >= threshold {
@@ -388,6 +1320,8 @@ impl Policy {
 
 #[cfg(test)]
 mod tests {
+    use std::convert::TryInto;
+
     use super::*;
 
     #[test]
@@ -401,4 +1335,369 @@ mod tests {
         let r2 = WeightOrRatio::Ratio(1, 1);
         assert_eq!(r2.to_weight(5), 5);
     }
+
+    #[test]
+    fn test_amount_thresholds() {
+        let mut vote_policy = VotePolicy::default();
+        vote_policy.amount_thresholds.insert(
+            BASE_TOKEN.to_string(),
+            vec![
+                AmountThreshold {
+                    max_amount: U128(10),
+                    threshold: WeightOrRatio::Weight(U128(1)),
+                },
+                AmountThreshold {
+                    max_amount: U128(100),
+                    threshold: WeightOrRatio::Ratio(2, 3),
+                },
+            ],
+        );
+        let near = BASE_TOKEN.to_string();
+        let token = "token.near".to_string();
+        assert_eq!(
+            vote_policy.threshold_for(Some(&(near.clone(), 10))),
+            &WeightOrRatio::Weight(U128(1))
+        );
+        assert_eq!(
+            vote_policy.threshold_for(Some(&(near.clone(), 11))),
+            &WeightOrRatio::Ratio(2, 3)
+        );
+        assert_eq!(
+            vote_policy.threshold_for(Some(&(near.clone(), 101))),
+            &WeightOrRatio::Ratio(1, 2)
+        );
+        assert_eq!(
+            vote_policy.threshold_for(Some(&(token.clone(), 1))),
+            &WeightOrRatio::Ratio(1, 2)
+        );
+        assert_eq!(vote_policy.threshold_for(None), &WeightOrRatio::Ratio(1, 2));
+        assert!(vote_policy.has_sorted_amount_thresholds());
+
+        let mut policy = default_policy(vec!["a.near".to_string()]);
+        vote_policy
+            .amount_thresholds
+            .get_mut(&near)
+            .unwrap()
+            .reverse();
+        policy.default_vote_policy = vote_policy;
+        assert_eq!(
+            policy.validate(),
+            Err(PolicyError::UnsortedAmountThresholds {
+                role: "all".to_string(),
+                proposal_kind: "*".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn test_policy_without_overrides() {
+        let policy: VersionedPolicy = near_sdk::serde_json::from_str(
+            r#"{
+                "roles": [],
+                "default_vote_policy": {"weight_kind": "RoleWeight", "quorum": "0", "threshold": [1, 2]},
+                "proposal_bond": "100",
+                "proposal_period": "1000",
+                "bounty_bond": "100",
+                "bounty_forgiveness_period": "1000"
+            }"#,
+        )
+        .unwrap();
+        let mut policy = policy.to_policy();
+        assert_eq!(policy.get_proposal_bond(&ProposalKind::Vote), 100);
+        assert_eq!(policy.get_proposal_period(&ProposalKind::Vote), 1000);
+        policy.proposal_bonds.insert("vote".to_string(), U128(10));
+        policy
+            .proposal_periods
+            .insert("vote".to_string(), WrappedDuration::from(50));
+        assert_eq!(policy.get_proposal_bond(&ProposalKind::Vote), 10);
+        assert_eq!(policy.get_proposal_period(&ProposalKind::Vote), 50);
+    }
+
+    #[test]
+    fn test_policy_v1() {
+        let council: HashSet<AccountId> = vec!["alice".to_string()].into_iter().collect();
+        let policy = VersionedPolicy::V1(PolicyV1 {
+            roles: vec![RolePermissionV1 {
+                name: "council".to_string(),
+                kind: RoleKind::Group(council.clone()),
+                permissions: vec!["*:*".to_string()].into_iter().collect(),
+                vote_policy: vec![(
+                    "vote".to_string(),
+                    VotePolicyV1 {
+                        weight_kind: WeightKind::RoleWeight,
+                        quorum: U128(1),
+                        threshold: WeightOrRatio::Ratio(1, 3),
+                    },
+                )]
+                .into_iter()
+                .collect(),
+            }],
+            default_vote_policy: VotePolicyV1 {
+                weight_kind: WeightKind::TokenWeight,
+                quorum: U128(0),
+                threshold: WeightOrRatio::Weight(U128(10)),
+            },
+            proposal_bond: U128(100),
+            proposal_period: WrappedDuration::from(1000),
+            bounty_bond: U128(10),
+            bounty_forgiveness_period: WrappedDuration::from(100),
+        });
+        // Previous version stored the policy as the second variant.
+        let bytes = policy.try_to_vec().unwrap();
+        assert_eq!(bytes[0], 1);
+        let policy = VersionedPolicy::try_from_slice(&bytes)
+            .unwrap()
+            .upgrade()
+            .to_policy();
+        assert_eq!(policy.roles[0].kind, RoleKind::Group(council));
+        assert_eq!(
+            policy.roles[0].vote_policy["vote"].threshold,
+            WeightOrRatio::Ratio(1, 3)
+        );
+        assert_eq!(policy.roles[0].parent, None);
+        assert_eq!(
+            policy.default_vote_policy.weight_kind,
+            WeightKind::TokenWeight
+        );
+        assert_eq!(policy.get_proposal_bond(&ProposalKind::Vote), 100);
+        assert_eq!(policy.proposal_bond_token, BASE_TOKEN);
+        assert_eq!(policy.bounty_forgiveness_period, WrappedDuration::from(100));
+        assert_eq!(VersionedPolicy::Current(policy).try_to_vec().unwrap()[0], 2);
+    }
+
+    #[test]
+    fn test_validate_policy() {
+        let policy = default_policy(vec!["a.near".to_string()]);
+        assert_eq!(policy.validate(), Ok(()));
+
+        let mut duplicate = policy.clone();
+        duplicate.roles[0].name = "council".to_string();
+        assert_eq!(
+            duplicate.validate(),
+            Err(PolicyError::DuplicateRole("council".to_string()))
+        );
+
+        let mut everyone_votes = policy.clone();
+        everyone_votes.roles[0]
+            .permissions
+            .insert("*:VoteApprove".to_string());
+        assert_eq!(
+            everyone_votes.validate(),
+            Err(PolicyError::UnsupportedRoleWeight {
+                role: "all".to_string(),
+                proposal_kind: "*".to_string(),
+            })
+        );
+
+        // Holders can't be counted, as tokens move between accounts.
+        let mut holders_vote = policy.clone();
+        holders_vote.roles.push(RolePermission {
+            name: "holders".to_string(),
+            kind: RoleKind::NftHolder {
+                contract_id: "nft.near".to_string(),
+                min_tokens: U128(1),
+            },
+            permissions: vec!["vote:VoteApprove".to_string()].into_iter().collect(),
+            scoped_permissions: vec![],
+            vote_policy: HashMap::default(),
+            allowances: HashMap::default(),
+            parent: None,
+        });
+        assert_eq!(
+            holders_vote.validate(),
+            Err(PolicyError::UnsupportedRoleWeight {
+                role: "holders".to_string(),
+                proposal_kind: "*".to_string(),
+            })
+        );
+        // They vote with their holding instead, decided by the quorum.
+        let holder_weight = VotePolicy {
+            weight_kind: WeightKind::HolderWeight,
+            quorum: U128(10),
+            ..VotePolicy::default()
+        };
+        holders_vote.default_vote_policy.weight_kind = WeightKind::TokenWeight;
+        holders_vote.roles[2]
+            .vote_policy
+            .insert("vote".to_string(), holder_weight.clone());
+        assert_eq!(holders_vote.validate(), Ok(()));
+        holders_vote.roles[2].vote_policy.insert(
+            "vote".to_string(),
+            VotePolicy {
+                quorum: U128(0),
+                ..holder_weight.clone()
+            },
+        );
+        assert_eq!(
+            holders_vote.validate(),
+            Err(PolicyError::UnsupportedHolderWeight {
+                role: "holders".to_string(),
+                proposal_kind: "vote".to_string(),
+            })
+        );
+        let mut council_holder_weight = policy.clone();
+        council_holder_weight.roles[1]
+            .vote_policy
+            .insert("vote".to_string(), holder_weight);
+        assert_eq!(
+            council_holder_weight.validate(),
+            Err(PolicyError::UnsupportedHolderWeight {
+                role: "council".to_string(),
+                proposal_kind: "vote".to_string(),
+            })
+        );
+
+        let mut zero_denominator = policy.clone();
+        zero_denominator.roles[1].vote_policy.insert(
+            "transfer".to_string(),
+            VotePolicy {
+                threshold: WeightOrRatio::Ratio(1, 0),
+                ..VotePolicy::default()
+            },
+        );
+        assert_eq!(
+            zero_denominator.validate(),
+            Err(PolicyError::ZeroDenominator {
+                role: "council".to_string(),
+                proposal_kind: "transfer".to_string(),
+            })
+        );
+
+        assert_eq!(
+            default_policy(vec![]).validate(),
+            Err(PolicyError::NoVoters)
+        );
+
+        let mut long_delay = policy.clone();
+        long_delay
+            .execution_delay
+            .insert("transfer".to_string(), WrappedDuration::from(u64::MAX));
+        assert_eq!(
+            long_delay.validate(),
+            Err(PolicyError::DurationTooLong(
+                "execution_delay:transfer".to_string()
+            ))
+        );
+
+        let mut long_grace_period = policy.clone();
+        long_grace_period.roles[1].vote_policy.insert(
+            "vote".to_string(),
+            VotePolicy {
+                optimistic: Some(OptimisticApproval {
+                    threshold: WeightOrRatio::Weight(U128(1)),
+                    grace_period: WrappedDuration::from(MAX_DURATION + 1),
+                }),
+                ..VotePolicy::default()
+            },
+        );
+        assert_eq!(
+            long_grace_period.validate(),
+            Err(PolicyError::DurationTooLong(
+                "grace_period:council:vote".to_string()
+            ))
+        );
+
+        let mut long_period = policy.clone();
+        long_period.proposal_period = WrappedDuration::from(MAX_DURATION + 1);
+        assert_eq!(
+            long_period.validate(),
+            Err(PolicyError::DurationTooLong("proposal_period".to_string()))
+        );
+
+        let mut long_kind_period = policy.clone();
+        long_kind_period
+            .proposal_periods
+            .insert("vote".to_string(), WrappedDuration::from(u64::MAX));
+        assert_eq!(
+            long_kind_period.validate(),
+            Err(PolicyError::DurationTooLong(
+                "proposal_periods:vote".to_string()
+            ))
+        );
+
+        let mut long_conviction = policy.clone();
+        long_conviction.roles[1].vote_policy.insert(
+            "vote".to_string(),
+            VotePolicy {
+                weight_kind: WeightKind::ConvictionWeight(WrappedDuration::from(u64::MAX)),
+                ..VotePolicy::default()
+            },
+        );
+        assert_eq!(
+            long_conviction.validate(),
+            Err(PolicyError::DurationTooLong(
+                "conviction_period:council:vote".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_role_inheritance() {
+        let mut policy = default_policy(vec!["a.near".to_string()]);
+        policy.roles[1].vote_policy.insert(
+            "transfer".to_string(),
+            VotePolicy {
+                threshold: WeightOrRatio::Weight(U128(1)),
+                ..VotePolicy::default()
+            },
+        );
+        policy.roles.push(RolePermission {
+            name: "admin".to_string(),
+            kind: RoleKind::Group(vec!["b.near".to_string()].into_iter().collect()),
+            permissions: vec!["*:RemoveProposal".to_string()].into_iter().collect(),
+            scoped_permissions: vec![],
+            vote_policy: HashMap::default(),
+            allowances: HashMap::default(),
+            parent: Some("council".to_string()),
+        });
+        assert_eq!(policy.validate(), Ok(()));
+        let user = |account_id: &str| UserInfo {
+            account_id: account_id.to_string(),
+            amount: 0,
+            large_groups: HashSet::default(),
+            holder_roles: HashMap::default(),
+        };
+        let transfer = ProposalKind::Transfer {
+            token_id: BASE_TOKEN.to_string(),
+            receiver_id: "c.near".to_string().try_into().unwrap(),
+            amount: U128(1),
+            msg: None,
+        };
+        assert_eq!(
+            policy.can_execute_action(user("b.near"), &transfer, &Action::VoteApprove),
+            (vec!["admin".to_string()], true)
+        );
+        assert_eq!(
+            policy.can_execute_action(user("b.near"), &transfer, &Action::RemoveProposal),
+            (vec!["admin".to_string()], true)
+        );
+        assert!(
+            !policy
+                .can_execute_action(user("a.near"), &transfer, &Action::RemoveProposal)
+                .1
+        );
+        assert_eq!(
+            policy
+                .internal_vote_policy(&policy.roles[2], &transfer)
+                .threshold,
+            WeightOrRatio::Weight(U128(1))
+        );
+
+        let mut cycle = policy.clone();
+        cycle.roles[1].parent = Some("admin".to_string());
+        assert_eq!(
+            cycle.validate(),
+            Err(PolicyError::RoleCycle("council".to_string()))
+        );
+
+        let mut unknown = policy.clone();
+        unknown.roles[2].parent = Some("core".to_string());
+        assert_eq!(
+            unknown.validate(),
+            Err(PolicyError::UnknownParent {
+                role: "admin".to_string(),
+                parent: "core".to_string(),
+            })
+        );
+    }
 }
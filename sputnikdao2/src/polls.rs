@@ -0,0 +1,533 @@
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{AccountId, Balance};
+
+use crate::policy::{Policy, WeightKind};
+use crate::proposals::{Proposal, ProposalKind};
+
+/// Max number of options in the poll. Votes are counted per option for each role,
+/// and instant runoff recounts ballots once per eliminated option.
+pub(crate) const MAX_POLL_OPTIONS: usize = 20;
+
+/// How votes of the poll are counted.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub enum PollTally {
+    /// Each voter picks one option, option with most votes wins.
+    Plurality,
+    /// Each voter picks any number of options, option with most votes wins.
+    Approval,
+    /// Each voter ranks options. Option with the least votes is eliminated and its votes
+    /// go to the next ranked option, until some option has majority.
+    InstantRunoff,
+}
+
+/// Option of the poll that executes given proposal kind if it wins.
+#[derive(Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Clone, Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct PollOption {
+    /// Label of the option.
+    pub label: String,
+    /// Proposal kind executed if this option wins.
+    pub kind: ProposalKind,
+}
+
+// `ProposalKind` contains options of the action poll, so the kind is stored as serialized bytes.
+// Derived implementations would recursively require themselves.
+impl BorshSerialize for PollOption {
+    fn serialize<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        BorshSerialize::serialize(&self.label, writer)?;
+        BorshSerialize::serialize(&self.kind.try_to_vec()?, writer)
+    }
+}
+
+impl BorshDeserialize for PollOption {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        let label = <String as BorshDeserialize>::deserialize(buf)?;
+        let kind = <Vec<u8> as BorshDeserialize>::deserialize(buf)?;
+        Ok(PollOption {
+            label,
+            kind: ProposalKind::try_from_slice(&kind)?,
+        })
+    }
+}
+
+impl PollTally {
+    /// Checks that given ballot is valid for this tally with given number of options.
+    pub fn assert_valid_ballot(&self, num_options: usize, ballot: &[u64]) {
+        let unique: HashSet<&u64> = ballot.iter().collect();
+        assert!(
+            !ballot.is_empty()
+                && unique.len() == ballot.len()
+                && ballot.iter().all(|option| (*option as usize) < num_options)
+                && (*self != PollTally::Plurality || ballot.len() == 1),
+            "ERR_INVALID_BALLOT"
+        );
+    }
+
+    /// Returns options of the ballot that are counted in `option_counts`.
+    /// For instant runoff only the first preference is counted there.
+    fn counted_options<'a>(&self, ballot: &'a [u64]) -> &'a [u64] {
+        match self {
+            PollTally::Approval => ballot,
+            PollTally::Plurality | PollTally::InstantRunoff => &ballot[..1],
+        }
+    }
+
+    /// Returns winning option given ballots with their weights, or None if there are no votes.
+    /// Ties are won by the option listed first.
+    pub fn winner(&self, num_options: usize, ballots: &[(&[u64], Balance)]) -> Option<u64> {
+        let mut eliminated = vec![false; num_options];
+        loop {
+            let mut counts = vec![0; num_options];
+            for (ballot, weight) in ballots {
+                match self {
+                    PollTally::Plurality | PollTally::Approval => {
+                        for option in self.counted_options(ballot) {
+                            counts[*option as usize] += weight;
+                        }
+                    }
+                    PollTally::InstantRunoff => {
+                        if let Some(option) =
+                            ballot.iter().find(|option| !eliminated[**option as usize])
+                        {
+                            counts[*option as usize] += weight;
+                        }
+                    }
+                }
+            }
+            let total: Balance = counts.iter().sum();
+            if total == 0 {
+                return None;
+            }
+            let remaining: Vec<usize> = (0..num_options).filter(|i| !eliminated[*i]).collect();
+            let leader = *remaining
+                .iter()
+                .max_by_key(|i| (counts[**i], Reverse(**i)))
+                .unwrap();
+            if *self != PollTally::InstantRunoff
+                || counts[leader] * 2 > total
+                || remaining.len() == 1
+            {
+                return Some(leader as u64);
+            }
+            let loser = *remaining
+                .iter()
+                .min_by_key(|i| (counts[**i], Reverse(**i)))
+                .unwrap();
+            eliminated[loser] = true;
+        }
+    }
+}
+
+impl Proposal {
+    /// Adds poll vote of the given user for given options, with weight given by `user_weight` for each role and its weight kind.
+    /// If user already voted, fails.
+    pub fn add_poll_vote(
+        &mut self,
+        account_id: &AccountId,
+        roles: &[String],
+        ballot: Vec<u64>,
+        policy: &Policy,
+        user_weight: impl Fn(&String, &WeightKind) -> Balance,
+    ) {
+        let (num_options, tally) = self.kind.poll().expect("ERR_NOT_POLL");
+        let tally = tally.clone();
+        tally.assert_valid_ballot(num_options, &ballot);
+        assert!(
+            !self.votes.contains_key(account_id) && !self.poll_votes.contains_key(account_id),
+            "ERR_ALREADY_VOTED"
+        );
+        let mut weights = HashMap::default();
+        for role in roles {
+            let amount = user_weight(
+                role,
+                policy.get_weight_kind(role, &self.kind.to_policy_label().to_string()),
+            );
+            let option_counts = self
+                .option_counts
+                .entry(role.clone())
+                .or_insert_with(|| vec![0; num_options]);
+            for option in tally.counted_options(&ballot) {
+                option_counts[*option as usize] += amount;
+            }
+            // Poll votes are not counted here, but status of the proposal is still checked for each role.
+            self.vote_counts.entry(role.clone()).or_insert([0u128; 3]);
+            weights.insert(role.clone(), amount);
+        }
+        self.poll_votes.insert(account_id.clone(), ballot);
+        self.vote_weights.insert(account_id.clone(), weights);
+    }
+
+    /// Removes poll vote of the given user with the weight it was counted with.
+    /// Returns false if user hasn't voted in the poll.
+    pub fn remove_poll_vote(&mut self, account_id: &AccountId) -> bool {
+        let tally = match self.kind.poll() {
+            Some((_, tally)) => tally.clone(),
+            None => return false,
+        };
+        let ballot = match self.poll_votes.remove(account_id) {
+            Some(ballot) => ballot,
+            None => return false,
+        };
+        for (role, amount) in self.vote_weights.remove(account_id).unwrap_or_default() {
+            let option_counts = self.option_counts.get_mut(&role).expect("ERR_MISSING_ROLE");
+            for option in tally.counted_options(&ballot) {
+                option_counts[*option as usize] -= amount;
+            }
+        }
+        true
+    }
+
+    /// Returns winning option of the poll for each role that voted in it.
+    /// Empty if this is not a poll.
+    pub fn poll_winners(&self) -> HashMap<String, u64> {
+        let (num_options, tally) = match self.kind.poll() {
+            Some(poll) => poll,
+            None => return HashMap::default(),
+        };
+        self.option_counts
+            .keys()
+            .filter_map(|role| {
+                let ballots: Vec<(&[u64], Balance)> = self
+                    .poll_votes
+                    .iter()
+                    .filter_map(|(account_id, ballot)| {
+                        let weight = self.vote_weights.get(account_id)?.get(role)?;
+                        Some((ballot.as_slice(), *weight))
+                    })
+                    .collect();
+                let winner = tally.winner(num_options, &ballots)?;
+                Some((role.clone(), winner))
+            })
+            .collect()
+    }
+
+    /// Returns total weight of poll votes of given role.
+    pub fn poll_turnout(&self, role: &String) -> Balance {
+        self.poll_votes
+            .keys()
+            .filter_map(|account_id| self.vote_weights.get(account_id)?.get(role))
+            .sum()
+    }
+
+    /// Returns kind that is executed when this proposal is approved.
+    /// For action poll it's the kind of the winning option.
+    pub fn executed_kind(&self) -> &ProposalKind {
+        match (&self.kind, self.winning_option) {
+            (ProposalKind::ActionPoll { options, .. }, Some(option)) => {
+                &options[option as usize].kind
+            }
+            _ => &self.kind,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::{WrappedDuration, U128};
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+    use near_sdk_sim::to_yocto;
+
+    use crate::policy::{VotePolicy, WeightOrRatio};
+    use crate::proposals::{ProposalInput, ProposalStatus};
+    use crate::types::BASE_TOKEN;
+    use crate::{Action, Config, Contract, VersionedPolicy};
+
+    use super::*;
+
+    #[test]
+    fn test_poll_tally() {
+        let ballots: Vec<(&[u64], Balance)> = vec![(&[0, 1], 1), (&[1, 0], 1), (&[2, 1], 1)];
+        // Tie is won by the first option.
+        assert_eq!(PollTally::Plurality.winner(3, &ballots), Some(0));
+        assert_eq!(PollTally::Approval.winner(3, &ballots), Some(1));
+        // Option 2 is eliminated and its vote goes to option 1.
+        assert_eq!(PollTally::InstantRunoff.winner(3, &ballots), Some(1));
+        assert_eq!(PollTally::InstantRunoff.winner(3, &ballots[..2]), Some(0));
+        assert_eq!(PollTally::Plurality.winner(3, &[]), None);
+        // Weights are taken into account.
+        let ballots: Vec<(&[u64], Balance)> = vec![(&[0], 1), (&[1], 5)];
+        assert_eq!(PollTally::Plurality.winner(2, &ballots), Some(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_BALLOT")]
+    fn test_plurality_single_option() {
+        PollTally::Plurality.assert_valid_ballot(3, &[0, 1]);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_BALLOT")]
+    fn test_ballot_duplicate_option() {
+        PollTally::InstantRunoff.assert_valid_ballot(3, &[0, 0]);
+    }
+
+    #[test]
+    fn test_poll_lifecycle() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![
+                accounts(1).into(),
+                accounts(2).into(),
+                accounts(3).into(),
+            ]),
+        );
+        testing_env!(context.attached_deposit(to_yocto("1")).build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "logo".to_string(),
+            kind: ProposalKind::Poll {
+                options: vec!["a".to_string(), "b".to_string(), "c".to_string()],
+                tally: PollTally::InstantRunoff,
+            },
+        });
+        contract.act_proposal(id, Action::VotePoll(vec![0, 1]), None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.act_proposal(id, Action::VotePoll(vec![1, 0]), None);
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.act_proposal(id, Action::VotePoll(vec![2]), None);
+        contract.act_proposal(id, Action::Unvote, None);
+        contract.act_proposal(id, Action::VotePoll(vec![2, 1]), None);
+        let output = contract.get_proposal(id);
+        assert_eq!(output.proposal.status, ProposalStatus::InProgress);
+        assert_eq!(output.proposal.option_counts["council"], vec![1, 1, 1]);
+        assert_eq!(output.poll_winners["council"], 1);
+
+        testing_env!(context
+            .block_timestamp(1_000_000_000 * 24 * 60 * 60 * 8)
+            .build());
+        contract.act_proposal(id, Action::Finalize, None);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Approved
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_POLL_VOTE_WITH_OPTIONS")]
+    fn test_poll_approve() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        testing_env!(context.attached_deposit(to_yocto("1")).build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "logo".to_string(),
+            kind: ProposalKind::Poll {
+                options: vec!["a".to_string(), "b".to_string()],
+                tally: PollTally::Plurality,
+            },
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+    }
+
+    fn transfer_option(amount: &str, msg: Option<String>) -> PollOption {
+        PollOption {
+            label: format!("{} NEAR", amount),
+            kind: ProposalKind::Transfer {
+                token_id: BASE_TOKEN.to_string(),
+                receiver_id: accounts(4),
+                amount: U128(to_yocto(amount)),
+                msg,
+            },
+        }
+    }
+
+    fn council_policy() -> VersionedPolicy {
+        VersionedPolicy::Default(vec![
+            accounts(1).into(),
+            accounts(2).into(),
+            accounts(3).into(),
+        ])
+        .upgrade()
+    }
+
+    fn setup_action_poll(context: &mut VMContextBuilder, options: Vec<PollOption>) -> Contract {
+        setup_action_poll_with_policy(context, council_policy(), options)
+    }
+
+    fn setup_action_poll_with_policy(
+        context: &mut VMContextBuilder,
+        policy: VersionedPolicy,
+        options: Vec<PollOption>,
+    ) -> Contract {
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(Config::test_config(), policy);
+        testing_env!(context.attached_deposit(to_yocto("1")).build());
+        contract.add_proposal(ProposalInput {
+            description: "budget".to_string(),
+            kind: ProposalKind::ActionPoll {
+                options,
+                tally: PollTally::Plurality,
+            },
+        });
+        contract
+    }
+
+    #[test]
+    fn test_action_poll() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = setup_action_poll(
+            &mut context,
+            vec![
+                transfer_option("1", None),
+                transfer_option("2", None),
+                transfer_option("3", None),
+            ],
+        );
+        contract.act_proposal(0, Action::VotePoll(vec![1]), None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.act_proposal(0, Action::VotePoll(vec![1]), None);
+        testing_env!(context
+            .block_timestamp(1_000_000_000 * 24 * 60 * 60 * 8)
+            .build());
+        contract.act_proposal(0, Action::Finalize, None);
+        let proposal = contract.get_proposal(0).proposal;
+        assert_eq!(proposal.status, ProposalStatus::Approved);
+        assert_eq!(proposal.winning_option, Some(1));
+        assert_eq!(proposal.executed_kind().to_policy_label(), "transfer");
+    }
+
+    #[test]
+    fn test_action_poll_low_turnout() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = setup_action_poll(
+            &mut context,
+            vec![transfer_option("1", None), transfer_option("2", None)],
+        );
+        contract.act_proposal(0, Action::VotePoll(vec![1]), None);
+        testing_env!(context
+            .block_timestamp(1_000_000_000 * 24 * 60 * 60 * 8)
+            .build());
+        contract.act_proposal(0, Action::Finalize, None);
+        let proposal = contract.get_proposal(0).proposal;
+        assert_eq!(proposal.status, ProposalStatus::Expired);
+        assert_eq!(proposal.winning_option, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_TOO_MANY_OPTIONS")]
+    fn test_poll_too_many_options() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        testing_env!(context.attached_deposit(to_yocto("1")).build());
+        contract.add_proposal(ProposalInput {
+            description: "logo".to_string(),
+            kind: ProposalKind::Poll {
+                options: (0..=MAX_POLL_OPTIONS).map(|i| i.to_string()).collect(),
+                tally: PollTally::Plurality,
+            },
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_BASE_TOKEN_NO_MSG")]
+    fn test_action_poll_invalid_option() {
+        let mut context = VMContextBuilder::new();
+        setup_action_poll(
+            &mut context,
+            vec![
+                transfer_option("1", None),
+                transfer_option("2", Some("msg".to_string())),
+            ],
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PERMISSION_DENIED")]
+    fn test_action_poll_option_not_allowed() {
+        let mut context = VMContextBuilder::new();
+        let mut policy = council_policy();
+        // Everyone can add action polls, but not transfers.
+        policy.to_policy_mut().roles[0].permissions = vec!["action_poll:AddProposal".to_string()]
+            .into_iter()
+            .collect();
+        let mut contract = setup_action_poll_with_policy(
+            &mut context,
+            policy,
+            vec![transfer_option("1", None), transfer_option("2", None)],
+        );
+        testing_env!(context.predecessor_account_id(accounts(5)).build());
+        contract.add_proposal(ProposalInput {
+            description: "budget".to_string(),
+            kind: ProposalKind::ActionPoll {
+                options: vec![transfer_option("1", None), transfer_option("2", None)],
+                tally: PollTally::Plurality,
+            },
+        });
+    }
+
+    #[test]
+    fn test_action_poll_option_vote_policy() {
+        let mut context = VMContextBuilder::new();
+        let mut policy = council_policy();
+        // Transfers need all of the council.
+        policy.to_policy_mut().roles[1].vote_policy.insert(
+            "transfer".to_string(),
+            VotePolicy {
+                threshold: WeightOrRatio::Weight(U128(3)),
+                ..VotePolicy::default()
+            },
+        );
+        policy
+            .to_policy_mut()
+            .execution_delay
+            .insert("transfer".to_string(), WrappedDuration::from(1_000));
+        let mut contract = setup_action_poll_with_policy(
+            &mut context,
+            policy,
+            vec![transfer_option("1", None), transfer_option("2", None)],
+        );
+        contract.act_proposal(0, Action::VotePoll(vec![1]), None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.act_proposal(0, Action::VotePoll(vec![1]), None);
+        testing_env!(context
+            .block_timestamp(1_000_000_000 * 24 * 60 * 60 * 8)
+            .build());
+        contract.act_proposal(0, Action::Finalize, None);
+        // Turnout reached the threshold of the poll, but not of the transfer.
+        assert_eq!(
+            contract.get_proposal(0).proposal.status,
+            ProposalStatus::Expired
+        );
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(0)
+            .build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "budget".to_string(),
+            kind: ProposalKind::ActionPoll {
+                options: vec![transfer_option("1", None), transfer_option("2", None)],
+                tally: PollTally::Plurality,
+            },
+        });
+        for account_id in 1..4 {
+            testing_env!(context.predecessor_account_id(accounts(account_id)).build());
+            contract.act_proposal(id, Action::VotePoll(vec![0]), None);
+        }
+        testing_env!(context
+            .block_timestamp(1_000_000_000 * 24 * 60 * 60 * 8)
+            .build());
+        contract.act_proposal(id, Action::Finalize, None);
+        // Winning transfer is delayed as transfers are.
+        let proposal = contract.get_proposal(id).proposal;
+        assert_eq!(proposal.status, ProposalStatus::Queued);
+        assert_eq!(proposal.winning_option, Some(0));
+    }
+}
@@ -3,15 +3,83 @@ use std::convert::TryFrom;
 
 use near_contract_standards::fungible_token::core_impl::ext_fungible_token;
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::json_types::{Base64VecU8, WrappedTimestamp, U64};
-use near_sdk::{log, AccountId, Balance, PromiseOrValue};
+use near_sdk::json_types::{Base64VecU8, WrappedDuration, WrappedTimestamp, U64};
+use near_sdk::serde_json::{self, json};
+use near_sdk::{ext_contract, log, AccountId, Balance, Gas, PromiseOrValue, PromiseResult};
 
-use crate::policy::UserInfo;
+use crate::policy::{UserInfo, WeightKind, MAX_DURATION};
+use crate::polls::{PollOption, PollTally, MAX_POLL_OPTIONS};
 use crate::types::{
-    upgrade_remote, upgrade_self, Action, Config, BASE_TOKEN, GAS_FOR_FT_TRANSFER, ONE_YOCTO_NEAR,
+    upgrade_remote, upgrade_self, Action, Config, BASE_TOKEN, GAS_FOR_CALL_STEP_CALLBACK,
+    GAS_FOR_FT_TRANSFER, GAS_FOR_HUB_POLICY_CALLBACK, GAS_FOR_HUB_POLICY_VIEW,
+    GAS_FOR_MOVE_PROPOSAL, GAS_FOR_PROPOSAL_CALLBACK, ONE_YOCTO_NEAR,
 };
 use crate::*;
 
+#[ext_contract(ext_self)]
+pub trait ExtSelf {
+    fn on_proposal_callback(&mut self, proposal_id: u64) -> PromiseOrValue<()>;
+    fn on_call_step_callback(&mut self, proposal_id: u64, step: u64) -> PromiseOrValue<()>;
+    fn on_hub_policy_callback(&mut self, proposal_id: u64, hub_id: AccountId)
+        -> PromiseOrValue<()>;
+    fn on_move_proposal_callback(&mut self, proposal_id: u64, hub_id: AccountId);
+    fn on_stream_withdraw_callback(&mut self, stream_id: u64, amount: U128);
+    fn on_recurring_transfer_callback(&mut self, recurring_transfer_id: u64, periods: u32);
+    fn on_spend_callback(
+        &mut self,
+        role: String,
+        token_id: AccountId,
+        amount: U128,
+        time: WrappedTimestamp,
+    );
+    fn on_sync_treasury_callback(&mut self, token_id: AccountId);
+    fn on_bounty_payout_callback(&mut self, token_id: AccountId, amount: U128);
+    fn on_add_proposal_as_holder(
+        &mut self,
+        proposer: AccountId,
+        proposal: ProposalInput,
+        role: String,
+        min_holding: U128,
+        deposit: U128,
+    ) -> Option<u64>;
+    fn on_proposal_added_as_holder(&mut self, proposer: AccountId, deposit: U128) -> Option<u64>;
+    fn on_act_proposal_as_holder(
+        &mut self,
+        sender_id: AccountId,
+        id: u64,
+        action: Action,
+        memo: Option<String>,
+        role: String,
+        min_holding: U128,
+    );
+}
+
+/// Part of the policy of the hub, required to add a proposal there.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct HubPolicy {
+    proposal_bond: U128,
+    #[serde(default)]
+    proposal_bond_token: AccountId,
+    #[serde(default)]
+    proposal_bonds: HashMap<String, U128>,
+}
+
+impl HubPolicy {
+    /// Returns bond the hub requires for given proposal kind or None if the hub takes bonds in tokens.
+    fn get_proposal_bond(&self, proposal_kind: &ProposalKind) -> Option<Balance> {
+        if self.proposal_bond_token != BASE_TOKEN {
+            return None;
+        }
+        Some(
+            self.proposal_bonds
+                .get(proposal_kind.to_policy_label())
+                .unwrap_or(&self.proposal_bond)
+                .0,
+        )
+    }
+}
+
 /// Status of a proposal.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, PartialEq, Debug)]
 #[serde(crate = "near_sdk::serde")]
@@ -28,6 +96,13 @@ pub enum ProposalStatus {
     Expired,
     /// If proposal was moved to Hub or somewhere else.
     Moved,
+    /// Approved, but waiting for the execution delay of its kind to pass before it can be executed.
+    Queued,
+    /// Approved, but execution failed. Bond is kept until execution is retried successfully.
+    Failed,
+    /// Reached optimistic threshold without reject votes, approved once the grace period is over.
+    /// Any reject vote returns it back in progress.
+    Delay,
 }
 
 /// Function call arguments.
@@ -41,6 +116,31 @@ pub struct ActionCall {
     gas: U64,
 }
 
+/// Creates a promise to `receiver_id` with given function calls in a single batch.
+fn function_call_promise(receiver_id: &ValidAccountId, actions: &[ActionCall]) -> Promise {
+    let mut promise = Promise::new(receiver_id.clone().into());
+    for action in actions {
+        promise = promise.function_call(
+            action.method_name.clone().into_bytes(),
+            action.args.clone().into(),
+            action.deposit.0,
+            action.gas.0,
+        )
+    }
+    promise
+}
+
+/// Gas required to execute given steps of the multi function call, including their callbacks.
+/// Returns None if it overflows.
+fn call_steps_gas(calls: &[(ValidAccountId, Vec<ActionCall>)]) -> Option<Gas> {
+    calls.iter().try_fold(0 as Gas, |total, (_, actions)| {
+        actions
+            .iter()
+            .try_fold(total, |total, action| total.checked_add(action.gas.0))?
+            .checked_add(GAS_FOR_CALL_STEP_CALLBACK)
+    })
+}
+
 /// Kinds of proposals, doing different action.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Clone, Debug))]
@@ -51,9 +151,12 @@ pub enum ProposalKind {
     /// Change the full policy.
     ChangePolicy { policy: VersionedPolicy },
     /// Add member to given role in the policy. This is short cut to updating the whole policy.
+    /// If `term` is given, membership expires after it since the execution. Adding existing member renews it.
     AddMemberToRole {
         member_id: ValidAccountId,
         role: String,
+        #[serde(default)]
+        term: Option<WrappedDuration>,
     },
     /// Remove member to given role in the policy. This is short cut to updating the whole policy.
     RemoveMemberFromRole {
@@ -95,6 +198,54 @@ pub enum ProposalKind {
     },
     /// Just a signaling vote, with no execution.
     Vote,
+    /// Creates a stream of `amount` of `token_id` vesting linearly from `start` to `end`,
+    /// which `receiver_id` withdraws via `stream_withdraw`. Nothing is vested before `cliff`.
+    Stream {
+        /// Can be "" for $NEAR or a valid account id.
+        token_id: AccountId,
+        receiver_id: ValidAccountId,
+        amount: U128,
+        start: WrappedTimestamp,
+        end: WrappedTimestamp,
+        cliff: Option<WrappedTimestamp>,
+    },
+    /// Stops given stream. Amount vested so far stays withdrawable, the rest returns to the treasury.
+    CancelStream { stream_id: u64 },
+    /// Schedules transfer of `amount` of `token_id` to `receiver_id` every `period`, `times` times.
+    /// First payment is due at `start`. Due payments are made via `trigger_recurring`.
+    RecurringTransfer {
+        /// Can be "" for $NEAR or a valid account id.
+        token_id: AccountId,
+        receiver_id: ValidAccountId,
+        amount: U128,
+        start: WrappedTimestamp,
+        period: WrappedDuration,
+        times: u32,
+    },
+    /// Signaling vote between labelled `options`, with no execution.
+    /// Members vote with `VotePoll` and votes are counted according to `tally`.
+    Poll {
+        options: Vec<String>,
+        tally: PollTally,
+    },
+    /// Poll between `options`, where kind of the winning option is executed.
+    /// Winner is taken from roles where total weight of poll votes reached the threshold.
+    ActionPoll {
+        options: Vec<PollOption>,
+        tally: PollTally,
+    },
+    /// Calls multiple receivers one after another, each with list of method names in a single promise.
+    /// Next step is only executed if the previous one succeeded.
+    MultiFunctionCall {
+        calls: Vec<(ValidAccountId, Vec<ActionCall>)>,
+    },
+    /// Moves given proposal of this DAO to the hub from the policy.
+    /// Bond required by the hub is paid by this DAO and refunded to it by the hub.
+    /// Proposer's bond isn't forwarded: this DAO is the proposer in the hub, so the hub's refund would
+    /// never reach them. It's returned to the proposer once the proposal is moved instead.
+    MoveToHub { proposal_id: u64 },
+    /// Stops given recurring transfer. Periods due so far can still be triggered, the rest returns to the treasury.
+    CancelRecurringTransfer { recurring_transfer_id: u64 },
 }
 
 impl ProposalKind {
@@ -106,13 +257,38 @@ impl ProposalKind {
             ProposalKind::AddMemberToRole { .. } => "add_member_to_role",
             ProposalKind::RemoveMemberFromRole { .. } => "remove_member_from_role",
             ProposalKind::FunctionCall { .. } => "call",
+            ProposalKind::MultiFunctionCall { .. } => "call",
             ProposalKind::UpgradeSelf { .. } => "upgrade_self",
             ProposalKind::UpgradeRemote { .. } => "upgrade_remote",
             ProposalKind::Transfer { .. } => "transfer",
             ProposalKind::SetStakingContract { .. } => "set_vote_token",
             ProposalKind::AddBounty { .. } => "add_bounty",
             ProposalKind::BountyDone { .. } => "bounty_done",
+            ProposalKind::Stream { .. } => "stream",
+            ProposalKind::CancelStream { .. } => "cancel_stream",
+            ProposalKind::RecurringTransfer { .. } => "recurring_transfer",
             ProposalKind::Vote => "vote",
+            ProposalKind::Poll { .. } => "poll",
+            ProposalKind::ActionPoll { .. } => "action_poll",
+            ProposalKind::MoveToHub { .. } => "move_to_hub",
+            ProposalKind::CancelRecurringTransfer { .. } => "cancel_recurring_transfer",
+        }
+    }
+
+    /// Returns number of options and tally if this is a poll.
+    pub fn poll(&self) -> Option<(usize, &PollTally)> {
+        match self {
+            ProposalKind::Poll { options, tally } => Some((options.len(), tally)),
+            ProposalKind::ActionPoll { options, tally } => Some((options.len(), tally)),
+            _ => None,
+        }
+    }
+
+    /// Returns true if execution of this kind is verified in `on_proposal_callback` and can be retried on failure.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            ProposalKind::FunctionCall { .. } | ProposalKind::Transfer { .. } => true,
+            _ => false,
         }
     }
 }
@@ -137,6 +313,16 @@ impl From<Action> for Vote {
     }
 }
 
+impl From<Vote> for Action {
+    fn from(vote: Vote) -> Self {
+        match vote {
+            Vote::Approve => Action::VoteApprove,
+            Vote::Reject => Action::VoteReject,
+            Vote::Remove => Action::VoteRemove,
+        }
+    }
+}
+
 /// Proposal that are sent to this DAO.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
@@ -154,49 +340,267 @@ pub struct Proposal {
     pub vote_counts: HashMap<String, [Balance; 3]>,
     /// Map of who voted and how.
     pub votes: HashMap<AccountId, Vote>,
+    /// Weight each vote was counted with per role. Used to subtract it when vote is changed or withdrawn.
+    pub vote_weights: HashMap<AccountId, HashMap<String, Balance>>,
     /// Submission time (for voting period).
     pub submission_time: WrappedTimestamp,
+    /// Token the bond was paid in. Can be "" for $NEAR or a valid account id.
+    pub bond_token: AccountId,
+    /// Bond paid for this proposal.
+    pub bond: U128,
+    /// When queued proposal can be executed via `execute_proposal`.
+    pub executable_at: Option<WrappedTimestamp>,
+    /// When delayed proposal is approved, if no one votes to reject it before.
+    pub grace_period_end: Option<WrappedTimestamp>,
+    /// Step of the `MultiFunctionCall` that failed. Retry resumes execution from it.
+    pub failed_step: Option<u64>,
+    /// Account and proposal id in it, where this proposal was moved.
+    pub moved_to: Option<(AccountId, u64)>,
+    /// Map of who voted in the poll and for which options, in order of preference.
+    pub poll_votes: HashMap<AccountId, Vec<u64>>,
+    /// Count of poll votes per role per option. For instant runoff only first preferences are counted.
+    pub option_counts: HashMap<String, Vec<Balance>>,
+    /// Option of the action poll that won and is executed.
+    pub winning_option: Option<u64>,
+}
+
+/// Kinds of proposals in the layout stored before `AddMemberToRole` got `term`.
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub enum ProposalKindV1 {
+    ChangeConfig {
+        config: Config,
+    },
+    ChangePolicy {
+        policy: VersionedPolicy,
+    },
+    AddMemberToRole {
+        member_id: ValidAccountId,
+        role: String,
+    },
+    RemoveMemberFromRole {
+        member_id: ValidAccountId,
+        role: String,
+    },
+    FunctionCall {
+        receiver_id: ValidAccountId,
+        actions: Vec<ActionCall>,
+    },
+    UpgradeSelf {
+        hash: Base58CryptoHash,
+    },
+    UpgradeRemote {
+        receiver_id: ValidAccountId,
+        method_name: String,
+        hash: Base58CryptoHash,
+    },
+    Transfer {
+        token_id: AccountId,
+        receiver_id: ValidAccountId,
+        amount: U128,
+        msg: Option<String>,
+    },
+    SetStakingContract {
+        staking_id: ValidAccountId,
+    },
+    AddBounty {
+        bounty: Bounty,
+    },
+    BountyDone {
+        bounty_id: u64,
+        receiver_id: ValidAccountId,
+    },
+    Vote,
+}
+
+impl From<ProposalKindV1> for ProposalKind {
+    fn from(kind: ProposalKindV1) -> Self {
+        match kind {
+            ProposalKindV1::ChangeConfig { config } => ProposalKind::ChangeConfig { config },
+            ProposalKindV1::ChangePolicy { policy } => ProposalKind::ChangePolicy {
+                policy: policy.upgrade(),
+            },
+            ProposalKindV1::AddMemberToRole { member_id, role } => ProposalKind::AddMemberToRole {
+                member_id,
+                role,
+                term: None,
+            },
+            ProposalKindV1::RemoveMemberFromRole { member_id, role } => {
+                ProposalKind::RemoveMemberFromRole { member_id, role }
+            }
+            ProposalKindV1::FunctionCall {
+                receiver_id,
+                actions,
+            } => ProposalKind::FunctionCall {
+                receiver_id,
+                actions,
+            },
+            ProposalKindV1::UpgradeSelf { hash } => ProposalKind::UpgradeSelf { hash },
+            ProposalKindV1::UpgradeRemote {
+                receiver_id,
+                method_name,
+                hash,
+            } => ProposalKind::UpgradeRemote {
+                receiver_id,
+                method_name,
+                hash,
+            },
+            ProposalKindV1::Transfer {
+                token_id,
+                receiver_id,
+                amount,
+                msg,
+            } => ProposalKind::Transfer {
+                token_id,
+                receiver_id,
+                amount,
+                msg,
+            },
+            ProposalKindV1::SetStakingContract { staking_id } => {
+                ProposalKind::SetStakingContract { staking_id }
+            }
+            ProposalKindV1::AddBounty { bounty } => ProposalKind::AddBounty { bounty },
+            ProposalKindV1::BountyDone {
+                bounty_id,
+                receiver_id,
+            } => ProposalKind::BountyDone {
+                bounty_id,
+                receiver_id,
+            },
+            ProposalKindV1::Vote => ProposalKind::Vote,
+        }
+    }
+}
+
+/// Proposal in the layout stored before bonds, delays, polls and vote weights were recorded in it.
+#[derive(BorshSerialize, BorshDeserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+pub struct ProposalV1 {
+    pub proposer: AccountId,
+    pub description: String,
+    pub kind: ProposalKindV1,
+    pub status: ProposalStatus,
+    pub vote_counts: HashMap<String, [Balance; 3]>,
+    pub votes: HashMap<AccountId, Vote>,
+    pub submission_time: WrappedTimestamp,
 }
 
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
 #[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
 #[serde(crate = "near_sdk::serde")]
 pub enum VersionedProposal {
-    Default(Proposal),
+    /// Proposal stored by the previous version of the contract. Upgraded when it's read.
+    #[serde(skip)]
+    Default(ProposalV1),
+    Current(Proposal),
 }
 
-impl From<VersionedProposal> for Proposal {
-    fn from(v: VersionedProposal) -> Self {
-        match v {
-            VersionedProposal::Default(p) => p,
+impl VersionedProposal {
+    /// Upgrades proposal into the latest version.
+    /// Proposals of the previous version were paid in $NEAR, `bond` returns the amount.
+    pub fn upgrade(self, bond: impl FnOnce() -> Balance) -> Proposal {
+        match self {
+            VersionedProposal::Default(proposal) => {
+                let kind = proposal.kind.into();
+                Proposal {
+                    proposer: proposal.proposer,
+                    description: proposal.description,
+                    bond: U128(bond()),
+                    kind,
+                    status: proposal.status,
+                    vote_counts: proposal.vote_counts,
+                    votes: proposal.votes,
+                    // Weights weren't recorded, such votes can't be changed or withdrawn.
+                    vote_weights: HashMap::default(),
+                    submission_time: proposal.submission_time,
+                    bond_token: BASE_TOKEN.to_string(),
+                    executable_at: None,
+                    grace_period_end: None,
+                    failed_step: None,
+                    moved_to: None,
+                    poll_votes: HashMap::default(),
+                    option_counts: HashMap::default(),
+                    winning_option: None,
+                }
+            }
+            VersionedProposal::Current(proposal) => proposal,
         }
     }
 }
 
+impl Contract {
+    /// Returns given proposal in the latest version.
+    /// Bond of the previous version's proposals is `proposal_bond` of the current policy, as it was refunded before.
+    pub(crate) fn internal_get_proposal(&self, id: u64) -> Option<Proposal> {
+        self.proposals.get(&id).map(|proposal| {
+            proposal.upgrade(|| self.policy.get().unwrap().to_policy().proposal_bond.0)
+        })
+    }
+}
+
 impl Proposal {
-    /// Adds vote of the given user with given `amount` of weight. If user already voted, fails.
+    /// Returns if this proposal can be moved to the hub: still in progress or waiting for the grace period.
+    pub fn is_movable(&self) -> bool {
+        matches!(
+            self.status,
+            ProposalStatus::InProgress | ProposalStatus::Delay
+        )
+    }
+
+    /// Returns status of this proposal before it was marked as moved.
+    /// Only proposals waiting for the grace period have its end set.
+    fn status_before_move(&self) -> ProposalStatus {
+        if self.grace_period_end.is_some() {
+            ProposalStatus::Delay
+        } else {
+            ProposalStatus::InProgress
+        }
+    }
+
+    /// Adds vote of the given user with weight given by `user_weight` for each role and its weight kind.
+    /// If user already voted, fails.
     pub fn update_votes(
         &mut self,
         account_id: &AccountId,
         roles: &[String],
         vote: Vote,
         policy: &Policy,
-        user_weight: Balance,
+        user_weight: impl Fn(&String, &WeightKind) -> Balance,
     ) {
+        assert!(
+            !self.poll_votes.contains_key(account_id),
+            "ERR_ALREADY_VOTED"
+        );
+        let mut weights = HashMap::default();
         for role in roles {
-            let amount = if policy.is_token_weighted(role, &self.kind.to_policy_label().to_string())
-            {
-                user_weight
-            } else {
-                1
-            };
+            let amount = user_weight(
+                role,
+                policy.get_weight_kind(role, &self.kind.to_policy_label().to_string()),
+            );
             self.vote_counts.entry(role.clone()).or_insert([0u128; 3])[vote.clone() as usize] +=
                 amount;
+            weights.insert(role.clone(), amount);
         }
         assert!(
             self.votes.insert(account_id.clone(), vote).is_none(),
             "ERR_ALREADY_VOTED"
         );
+        self.vote_weights.insert(account_id.clone(), weights);
+    }
+
+    /// Removes vote of the given user with the weight it was counted with. If user hasn't voted, fails.
+    /// Fails for votes cast before weights were recorded, as they can't be subtracted.
+    pub fn remove_vote(&mut self, account_id: &AccountId) {
+        let vote = self.votes.remove(account_id).expect("ERR_NO_VOTE");
+        let weights = self
+            .vote_weights
+            .remove(account_id)
+            .expect("ERR_VOTE_WEIGHT_UNKNOWN");
+        for (role, amount) in weights {
+            let vote_counts = self.vote_counts.entry(role).or_insert([0u128; 3]);
+            vote_counts[vote.clone() as usize] =
+                vote_counts[vote.clone() as usize].saturating_sub(amount);
+        }
     }
 }
 
@@ -209,20 +613,6 @@ pub struct ProposalInput {
     pub kind: ProposalKind,
 }
 
-impl From<ProposalInput> for Proposal {
-    fn from(input: ProposalInput) -> Self {
-        Self {
-            proposer: env::predecessor_account_id(),
-            description: input.description,
-            kind: input.kind,
-            status: ProposalStatus::InProgress,
-            vote_counts: HashMap::default(),
-            votes: HashMap::default(),
-            submission_time: WrappedTimestamp::from(env::block_timestamp()),
-        }
-    }
-}
-
 impl Contract {
     /// Execute payout of given token to given user.
     pub(crate) fn internal_payout(
@@ -261,49 +651,84 @@ impl Contract {
         }
     }
 
+    /// Unlocks $NEAR bond of given proposal, once it's returned or kept by this DAO.
+    fn internal_unlock_bond(&mut self, id: u64, proposal: &Proposal) {
+        if proposal.bond_token == BASE_TOKEN && id >= self.first_locked_bond_id {
+            self.locked_amount = self.locked_amount.saturating_sub(proposal.bond.0);
+        }
+    }
+
+    /// Returns the proposal bond to the proposer in the token it was paid in.
+    fn internal_return_bond(&mut self, id: u64, proposal: &Proposal) -> PromiseOrValue<()> {
+        self.internal_unlock_bond(id, proposal);
+        self.internal_payout(
+            &proposal.bond_token,
+            &proposal.proposer,
+            proposal.bond.0,
+            "Proposal bond".to_string(),
+            None,
+        )
+    }
+
     /// Executes given proposal and updates the contract's state.
+    /// Bond is returned right away, unless execution must first be verified in `on_proposal_callback`.
     fn internal_execute_proposal(
         &mut self,
         policy: &Policy,
         proposal: &Proposal,
+        proposal_id: u64,
     ) -> PromiseOrValue<()> {
-        // Return the proposal bond.
-        Promise::new(proposal.proposer.clone()).transfer(policy.proposal_bond.0);
-        match &proposal.kind {
+        let result = match proposal.executed_kind() {
             ProposalKind::ChangeConfig { config } => {
                 self.config.set(config);
                 PromiseOrValue::Value(())
             }
-            ProposalKind::ChangePolicy { policy } => {
-                self.policy.set(policy);
+            ProposalKind::ChangePolicy { policy: new_policy } => {
+                let new_policy = new_policy.clone().upgrade().to_policy();
+                self.internal_remove_large_groups(policy, &new_policy);
+                self.policy.set(&VersionedPolicy::Current(new_policy));
                 PromiseOrValue::Value(())
             }
-            ProposalKind::AddMemberToRole { member_id, role } => {
-                let mut new_policy = policy.clone();
-                new_policy.add_member_to_role(role, &member_id.clone().into());
-                self.policy.set(&VersionedPolicy::Current(new_policy));
+            ProposalKind::AddMemberToRole {
+                member_id,
+                role,
+                term,
+            } => {
+                if policy.is_large_group(role) && term.is_none() {
+                    self.internal_add_large_group_member(role, &member_id.clone().into());
+                } else {
+                    let mut new_policy = policy.clone();
+                    new_policy.add_member_to_role(
+                        role,
+                        &member_id.clone().into(),
+                        // Term too long to fit the timestamp never ends.
+                        term.map(|term| env::block_timestamp().saturating_add(term.0)),
+                    );
+                    self.policy.set(&VersionedPolicy::Current(new_policy));
+                }
                 PromiseOrValue::Value(())
             }
             ProposalKind::RemoveMemberFromRole { member_id, role } => {
-                let mut new_policy = policy.clone();
-                new_policy.remove_member_from_role(role, &member_id.clone().into());
-                self.policy.set(&VersionedPolicy::Current(new_policy));
+                if policy.is_large_group(role) {
+                    self.internal_remove_large_group_member(role, &member_id.clone().into());
+                } else {
+                    let mut new_policy = policy.clone();
+                    new_policy.remove_member_from_role(role, &member_id.clone().into());
+                    self.policy.set(&VersionedPolicy::Current(new_policy));
+                }
                 PromiseOrValue::Value(())
             }
             ProposalKind::FunctionCall {
                 receiver_id,
                 actions,
-            } => {
-                let mut promise = Promise::new(receiver_id.clone().into());
-                for action in actions {
-                    promise = promise.function_call(
-                        action.method_name.clone().into_bytes(),
-                        action.args.clone().into(),
-                        action.deposit.0,
-                        action.gas.0,
-                    )
-                }
-                promise.into()
+            } => function_call_promise(receiver_id, actions).into(),
+            ProposalKind::MultiFunctionCall { calls } => {
+                // Each step is verified in `on_call_step_callback`, which also returns the bond.
+                return self.internal_execute_call_step(
+                    proposal_id,
+                    calls,
+                    proposal.failed_step.unwrap_or_default() as usize,
+                );
             }
             ProposalKind::UpgradeSelf { hash } => {
                 upgrade_self(&CryptoHash::from(hash.clone()));
@@ -346,20 +771,175 @@ impl Contract {
                 bounty_id,
                 receiver_id,
             } => self.internal_execute_bounty_payout(*bounty_id, &receiver_id.clone().into(), true),
-            ProposalKind::Vote => PromiseOrValue::Value(()),
+            ProposalKind::Stream {
+                token_id,
+                receiver_id,
+                amount,
+                start,
+                end,
+                cliff,
+            } => {
+                self.internal_add_stream(&Stream {
+                    token_id: token_id.clone(),
+                    receiver_id: receiver_id.clone().into(),
+                    amount: *amount,
+                    start: *start,
+                    end: *end,
+                    cliff: *cliff,
+                    withdrawn: U128(0),
+                });
+                PromiseOrValue::Value(())
+            }
+            ProposalKind::CancelStream { stream_id } => {
+                self.internal_cancel_stream(*stream_id);
+                PromiseOrValue::Value(())
+            }
+            ProposalKind::RecurringTransfer {
+                token_id,
+                receiver_id,
+                amount,
+                start,
+                period,
+                times,
+            } => {
+                self.internal_add_recurring_transfer(&RecurringTransfer {
+                    token_id: token_id.clone(),
+                    receiver_id: receiver_id.clone().into(),
+                    amount: *amount,
+                    start: *start,
+                    period: *period,
+                    times: *times,
+                    paid_periods: 0,
+                });
+                PromiseOrValue::Value(())
+            }
+            ProposalKind::MoveToHub {
+                proposal_id: moved_id,
+            } => self.internal_move_proposal(policy, *moved_id),
+            ProposalKind::CancelRecurringTransfer {
+                recurring_transfer_id,
+            } => {
+                self.internal_cancel_recurring_transfer(*recurring_transfer_id);
+                PromiseOrValue::Value(())
+            }
+            ProposalKind::Vote | ProposalKind::Poll { .. } | ProposalKind::ActionPoll { .. } => {
+                PromiseOrValue::Value(())
+            }
+        };
+        match result {
+            PromiseOrValue::Promise(promise) if proposal.executed_kind().is_retryable() => promise
+                .then(ext_self::on_proposal_callback(
+                    proposal_id,
+                    &env::current_account_id(),
+                    0,
+                    GAS_FOR_PROPOSAL_CALLBACK,
+                ))
+                .into(),
+            result => {
+                self.internal_return_bond(proposal_id, proposal);
+                result
+            }
+        }
+    }
+
+    /// Executes given step of the multi function call and schedules verification of it.
+    fn internal_execute_call_step(
+        &mut self,
+        proposal_id: u64,
+        calls: &[(ValidAccountId, Vec<ActionCall>)],
+        step: usize,
+    ) -> PromiseOrValue<()> {
+        let (receiver_id, actions) = &calls[step];
+        function_call_promise(receiver_id, actions)
+            .then(ext_self::on_call_step_callback(
+                proposal_id,
+                step as u64,
+                &env::current_account_id(),
+                0,
+                // Doesn't overflow, total gas of all the steps is checked when proposal is added.
+                GAS_FOR_CALL_STEP_CALLBACK + call_steps_gas(&calls[step + 1..]).unwrap(),
+            ))
+            .into()
+    }
+
+    /// Marks given proposal as moved and reads the policy of the hub from given policy,
+    /// to add the proposal there with the bond the hub requires in `on_hub_policy_callback`.
+    /// Doesn't fail, because is executed by the approved `MoveToHub` proposal.
+    fn internal_move_proposal(&mut self, policy: &Policy, proposal_id: u64) -> PromiseOrValue<()> {
+        let hub_id = match policy.hub_id.clone() {
+            Some(hub_id) => hub_id,
+            None => {
+                env::log(b"ERR_NO_HUB");
+                return PromiseOrValue::Value(());
+            }
+        };
+        let mut proposal = match self.internal_get_proposal(proposal_id) {
+            Some(proposal) => proposal,
+            None => {
+                env::log(b"ERR_NO_PROPOSAL");
+                return PromiseOrValue::Value(());
+            }
+        };
+        if !proposal.is_movable() {
+            env::log(b"ERR_PROPOSAL_NOT_IN_PROGRESS");
+            return PromiseOrValue::Value(());
+        }
+        // Marked as moved right away to stop voting, reverted in the callbacks on failure.
+        proposal.status = ProposalStatus::Moved;
+        self.proposals
+            .insert(&proposal_id, &VersionedProposal::Current(proposal));
+        Promise::new(hub_id.clone())
+            .function_call(
+                b"get_policy".to_vec(),
+                b"{}".to_vec(),
+                0,
+                GAS_FOR_HUB_POLICY_VIEW,
+            )
+            .then(ext_self::on_hub_policy_callback(
+                proposal_id,
+                hub_id,
+                &env::current_account_id(),
+                0,
+                GAS_FOR_HUB_POLICY_CALLBACK,
+            ))
+            .into()
+    }
+
+    /// Process approving proposal: reserves its payout, then queues or executes it.
+    /// If there are not enough tokens to pay out, marks it as `Failed` to be retried later.
+    fn internal_approve_proposal(&mut self, policy: &Policy, proposal: &mut Proposal, id: u64) {
+        proposal.status = ProposalStatus::Approved;
+        let delay = policy.execution_delay(proposal);
+        if delay > 0 {
+            proposal.executable_at = Some(WrappedTimestamp::from(
+                env::block_timestamp().saturating_add(delay),
+            ));
+        }
+        // Tokens to pay out must be in the treasury and not promised to other proposals.
+        if !self.internal_reserve_for_proposal(proposal.executed_kind()) {
+            log!("ERR_NOT_ENOUGH_TOKENS");
+            proposal.status = ProposalStatus::Failed;
+        } else if delay > 0 {
+            // Queue the proposal to give time to members who disagree to exit.
+            proposal.status = ProposalStatus::Queued;
+        } else {
+            self.internal_execute_proposal(policy, proposal, id);
         }
     }
 
     /// Process rejecting proposal.
     fn internal_reject_proposal(
         &mut self,
-        policy: &Policy,
+        id: u64,
         proposal: &Proposal,
         return_bond: bool,
     ) -> PromiseOrValue<()> {
         if return_bond {
             // Return bond to the proposer.
-            Promise::new(proposal.proposer.clone()).transfer(policy.proposal_bond.0);
+            self.internal_return_bond(id, proposal);
+        } else {
+            // Bond is kept by this DAO.
+            self.internal_unlock_bond(id, proposal);
         }
         match &proposal.kind {
             ProposalKind::BountyDone {
@@ -372,30 +952,41 @@ impl Contract {
         }
     }
 
-    pub(crate) fn internal_user_info(&self) -> UserInfo {
-        let account_id = env::predecessor_account_id();
+    pub(crate) fn internal_user_info(&self, policy: &Policy, account_id: &AccountId) -> UserInfo {
         UserInfo {
-            amount: self.get_user_weight(&account_id),
-            account_id,
+            amount: self.get_user_weight(account_id),
+            account_id: account_id.clone(),
+            large_groups: self.internal_large_groups_of(policy, account_id),
+            holder_roles: HashMap::default(),
         }
     }
-}
 
-#[near_bindgen]
-impl Contract {
-    /// Add proposal to this DAO.
-    #[payable]
-    pub fn add_proposal(&mut self, proposal: ProposalInput) -> u64 {
-        // 0. validate bond attached.
-        // TODO: consider bond in the token of this DAO.
-        let policy = self.policy.get().unwrap().to_policy();
-        assert!(
-            env::attached_deposit() >= policy.proposal_bond.0,
-            "ERR_MIN_BOND"
-        );
+    /// Returns token and amount paid out by given proposal kind, which selects the threshold tier.
+    fn internal_proposal_payout(&self, kind: &ProposalKind) -> Option<(AccountId, Balance)> {
+        match kind {
+            ProposalKind::Transfer {
+                token_id, amount, ..
+            } => Some((token_id.clone(), amount.0)),
+            ProposalKind::BountyDone { bounty_id, .. } => {
+                let bounty: Bounty = self.bounties.get(bounty_id)?.into();
+                Some((bounty.token, bounty.amount.0))
+            }
+            ProposalKind::Stream {
+                token_id, amount, ..
+            } => Some((token_id.clone(), amount.0)),
+            ProposalKind::RecurringTransfer {
+                token_id,
+                amount,
+                times,
+                ..
+            } => Some((token_id.clone(), amount.0.saturating_mul(*times as u128))),
+            _ => None,
+        }
+    }
 
-        // 1. Validate proposal.
-        match &proposal.kind {
+    /// Validates given proposal kind. Fails if it's malformed.
+    pub(crate) fn internal_validate_proposal_kind(&self, kind: &ProposalKind) {
+        match kind {
             ProposalKind::Transfer { token_id, msg, .. } => {
                 assert!(
                     !(token_id == BASE_TOKEN) || msg.is_none(),
@@ -408,104 +999,604 @@ impl Contract {
                     );
                 }
             }
+            ProposalKind::ChangePolicy { policy } => {
+                let mut policy = policy.clone().upgrade().to_policy();
+                self.internal_sync_large_groups(&mut policy);
+                policy.assert_valid()
+            }
+            ProposalKind::AddMemberToRole { role, term, .. } => {
+                let policy = self.policy.get().unwrap().to_policy();
+                assert!(
+                    term.is_none() || !policy.is_large_group(role),
+                    "ERR_LARGE_GROUP_TERM"
+                );
+            }
+            ProposalKind::MoveToHub { proposal_id } => {
+                let policy = self.policy.get().unwrap().to_policy();
+                assert!(policy.hub_id.is_some(), "ERR_NO_HUB");
+                let proposal = self
+                    .internal_get_proposal(*proposal_id)
+                    .expect("ERR_NO_PROPOSAL");
+                assert!(proposal.is_movable(), "ERR_PROPOSAL_NOT_IN_PROGRESS");
+            }
+            ProposalKind::MultiFunctionCall { calls } => {
+                assert!(!calls.is_empty(), "ERR_NO_CALLS");
+                assert!(call_steps_gas(calls).is_some(), "ERR_GAS_OVERFLOW");
+            }
+            ProposalKind::Stream {
+                token_id,
+                amount,
+                start,
+                end,
+                cliff,
+                ..
+            } => {
+                if token_id != BASE_TOKEN {
+                    assert!(
+                        ValidAccountId::try_from(token_id.clone()).is_ok(),
+                        "ERR_TOKEN_ID_INVALID"
+                    );
+                }
+                assert!(
+                    amount.0 > 0 && start.0 < end.0 && end.0 - start.0 <= MAX_DURATION,
+                    "ERR_INVALID_STREAM"
+                );
+                if let Some(cliff) = cliff {
+                    assert!(start.0 <= cliff.0 && cliff.0 <= end.0, "ERR_INVALID_STREAM");
+                }
+            }
+            ProposalKind::RecurringTransfer {
+                token_id,
+                amount,
+                period,
+                times,
+                ..
+            } => {
+                if token_id != BASE_TOKEN {
+                    assert!(
+                        ValidAccountId::try_from(token_id.clone()).is_ok(),
+                        "ERR_TOKEN_ID_INVALID"
+                    );
+                }
+                assert!(
+                    period.0 > 0 && *times > 0 && amount.0.checked_mul(*times as u128).is_some(),
+                    "ERR_INVALID_RECURRING_TRANSFER"
+                );
+            }
+            ProposalKind::Poll { options, .. } => {
+                assert!(options.len() >= 2, "ERR_NOT_ENOUGH_OPTIONS");
+                assert!(options.len() <= MAX_POLL_OPTIONS, "ERR_TOO_MANY_OPTIONS");
+            }
+            ProposalKind::ActionPoll { options, .. } => {
+                assert!(options.len() >= 2, "ERR_NOT_ENOUGH_OPTIONS");
+                assert!(options.len() <= MAX_POLL_OPTIONS, "ERR_TOO_MANY_OPTIONS");
+                for option in options {
+                    assert!(option.kind.poll().is_none(), "ERR_NESTED_POLL");
+                    self.internal_validate_proposal_kind(&option.kind);
+                }
+            }
+            ProposalKind::CancelStream { stream_id } => {
+                let stream: Stream = self.streams.get(stream_id).expect("ERR_NO_STREAM").into();
+                assert!(env::block_timestamp() < stream.end.0, "ERR_STREAM_ENDED");
+            }
+            ProposalKind::CancelRecurringTransfer {
+                recurring_transfer_id,
+            } => {
+                let recurring_transfer: RecurringTransfer = self
+                    .recurring_transfers
+                    .get(recurring_transfer_id)
+                    .expect("ERR_NO_RECURRING_TRANSFER")
+                    .into();
+                assert!(
+                    recurring_transfer.due_periods(env::block_timestamp())
+                        < recurring_transfer.times,
+                    "ERR_RECURRING_TRANSFER_ENDED"
+                );
+            }
             ProposalKind::SetStakingContract { .. } => assert!(
                 self.staking_id.is_none(),
                 "ERR_STAKING_CONTRACT_CANT_CHANGE"
             ),
-            // TODO: add more verifications.
-            _ => {}
-        };
+            ProposalKind::ChangeConfig { .. }
+            | ProposalKind::RemoveMemberFromRole { .. }
+            | ProposalKind::FunctionCall { .. }
+            | ProposalKind::UpgradeSelf { .. }
+            | ProposalKind::UpgradeRemote { .. }
+            | ProposalKind::AddBounty { .. }
+            | ProposalKind::BountyDone { .. }
+            | ProposalKind::Vote => {}
+        }
+    }
+
+    /// Validates and adds proposal from given proposer, who paid the bond required by the policy.
+    /// `holder_roles` are token gated roles with the holding verified for this proposal.
+    pub(crate) fn internal_add_proposal(
+        &mut self,
+        proposer: AccountId,
+        proposal: ProposalInput,
+        policy: &Policy,
+        holder_roles: HashMap<String, Balance>,
+    ) -> u64 {
+        // 1. Validate proposal.
+        self.internal_validate_proposal_kind(&proposal.kind);
 
-        // 2. Check permission of caller to add this type of proposal.
+        // 2. Check permission of proposer to add this type of proposal.
         assert!(
-            policy
-                .can_execute_action(
-                    self.internal_user_info(),
-                    &proposal.kind,
-                    &Action::AddProposal
-                )
-                .1,
+            policy.can_add_proposal(
+                &UserInfo {
+                    holder_roles,
+                    ..self.internal_user_info(&policy, &proposer)
+                },
+                &proposal.kind
+            ),
             "ERR_PERMISSION_DENIED"
         );
 
         // 3. Actually add proposal to the current list of proposals.
         let id = self.last_proposal_id;
-        self.proposals
-            .insert(&id, &VersionedProposal::Default(proposal.into()));
+        let bond = U128(policy.get_proposal_bond(&proposal.kind));
+        if policy.proposal_bond_token == BASE_TOKEN {
+            // Bond belongs to the proposer until it's returned or kept.
+            self.locked_amount += bond.0;
+        }
+        self.proposals.insert(
+            &id,
+            &VersionedProposal::Current(Proposal {
+                proposer,
+                description: proposal.description,
+                kind: proposal.kind,
+                status: ProposalStatus::InProgress,
+                vote_counts: HashMap::default(),
+                votes: HashMap::default(),
+                vote_weights: HashMap::default(),
+                submission_time: WrappedTimestamp::from(env::block_timestamp()),
+                bond_token: policy.proposal_bond_token.clone(),
+                bond,
+                executable_at: None,
+                grace_period_end: None,
+                failed_step: None,
+                moved_to: None,
+                poll_votes: HashMap::default(),
+                option_counts: HashMap::default(),
+                winning_option: None,
+            }),
+        );
         self.last_proposal_id += 1;
         id
     }
 
-    /// Act on given proposal by id, if permissions allow.
-    /// Memo is logged but not stored in the state. Can be used to leave notes or explain the action.
-    pub fn act_proposal(&mut self, id: u64, action: Action, memo: Option<String>) {
-        let mut proposal: Proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL").into();
-        let policy = self.policy.get().unwrap().to_policy();
+    /// Acts on given proposal by id from given sender, if permissions allow.
+    /// `holder_roles` are token gated roles with the holding verified for this action, which is the weight
+    /// of their votes with `HolderWeight`.
+    pub(crate) fn internal_act_proposal(
+        &mut self,
+        sender_id: AccountId,
+        holder_roles: HashMap<String, Balance>,
+        id: u64,
+        action: Action,
+        memo: Option<String>,
+    ) {
+        let mut proposal: Proposal = self.internal_get_proposal(id).expect("ERR_NO_PROPOSAL");
+        let policy = self.internal_policy();
         // Check permissions for the given action.
-        let (roles, allowed) =
-            policy.can_execute_action(self.internal_user_info(), &proposal.kind, &action);
-        assert!(allowed, "ERR_PERMISSION_DENIED");
-        let sender_id = env::predecessor_account_id();
+        let user = UserInfo {
+            holder_roles: holder_roles.clone(),
+            ..self.internal_user_info(&policy, &sender_id)
+        };
+        let (mut roles, _) = policy.can_execute_action(user.clone(), &proposal.kind, &action);
+        if let Action::ChangeVote(vote) = &action {
+            // New vote is counted only for roles that are allowed to cast it.
+            let (vote_roles, _) =
+                policy.can_execute_action(user, &proposal.kind, &Action::from(vote.clone()));
+            roles.retain(|role| vote_roles.contains(role));
+        }
+        assert!(!roles.is_empty(), "ERR_PERMISSION_DENIED");
+        // Token weights are taken at the time of submission, so moving delegation doesn't allow to vote twice.
+        let submission_time = proposal.submission_time.0;
+        let total_supply =
+            |weight_kind: &WeightKind| self.get_total_vote_weight_at(weight_kind, submission_time);
+        let payout = |kind: &ProposalKind| self.internal_proposal_payout(kind);
+        let user_weight = |role: &String, weight_kind: &WeightKind| match weight_kind {
+            WeightKind::HolderWeight => holder_roles.get(role).copied().unwrap_or_default(),
+            weight_kind => self.get_vote_weight_at(&sender_id, weight_kind, submission_time),
+        };
         // Update proposal given action. Returns true if should be updated in storage.
         let update = match action {
             Action::AddProposal => env::panic(b"ERR_WRONG_ACTION"),
             Action::RemoveProposal => {
+                // Execution verified in a callback could still be pending, the callback needs the proposal
+                // to settle the treasury.
+                assert!(
+                    proposal.status != ProposalStatus::Approved
+                        || !matches!(
+                            proposal.executed_kind(),
+                            ProposalKind::Transfer { .. }
+                                | ProposalKind::FunctionCall { .. }
+                                | ProposalKind::MultiFunctionCall { .. }
+                        ),
+                    "ERR_PROPOSAL_EXECUTING"
+                );
+                if proposal.status == ProposalStatus::Queued {
+                    self.internal_release_for_proposal(proposal.executed_kind());
+                }
                 self.proposals.remove(&id);
                 false
             }
-            Action::VoteApprove | Action::VoteReject | Action::VoteRemove => {
-                assert_eq!(
-                    proposal.status,
-                    ProposalStatus::InProgress,
+            Action::VoteApprove
+            | Action::VoteReject
+            | Action::VoteRemove
+            | Action::ChangeVote(_)
+            | Action::Unvote
+            | Action::VotePoll(_) => {
+                assert!(
+                    proposal.status == ProposalStatus::InProgress
+                        || proposal.status == ProposalStatus::Delay,
                     "ERR_PROPOSAL_NOT_IN_PROGRESS"
                 );
-                proposal.update_votes(
-                    &sender_id,
-                    &roles,
-                    Vote::from(action),
-                    &policy,
-                    self.get_user_weight(&sender_id),
-                );
-                // Updates proposal status with new votes using the policy.
-                proposal.status =
-                    policy.proposal_status(&proposal, roles, self.total_delegation_amount);
+                // Votes after the voting period don't count.
+                if !policy.is_proposal_expired(&proposal) {
+                    let vote = match action {
+                        Action::ChangeVote(vote) => {
+                            proposal.remove_vote(&sender_id);
+                            Some(vote)
+                        }
+                        Action::Unvote => {
+                            if !proposal.remove_poll_vote(&sender_id) {
+                                proposal.remove_vote(&sender_id);
+                            }
+                            None
+                        }
+                        Action::VotePoll(ballot) => {
+                            proposal.add_poll_vote(
+                                &sender_id,
+                                &roles,
+                                ballot,
+                                &policy,
+                                user_weight,
+                            );
+                            None
+                        }
+                        action => Some(Vote::from(action)),
+                    };
+                    if let Some(vote) = vote {
+                        // Polls are decided by `VotePoll`, but still can be removed as spam.
+                        if proposal.kind.poll().is_some() {
+                            assert!(matches!(vote, Vote::Remove), "ERR_POLL_VOTE_WITH_OPTIONS");
+                        }
+                        proposal.update_votes(&sender_id, &roles, vote, &policy, user_weight);
+                    }
+                }
+                if proposal.status == ProposalStatus::Delay && policy.is_proposal_expired(&proposal)
+                {
+                    // Votes can't contest it anymore, it's approved once the grace period is over.
+                    if env::block_timestamp() >= proposal.grace_period_end.unwrap().0 {
+                        proposal.status = ProposalStatus::Approved;
+                    }
+                } else {
+                    // Updates proposal status with new votes using the policy.
+                    proposal.status =
+                        policy.proposal_status(&proposal, roles, &total_supply, &payout);
+                    if proposal.status == ProposalStatus::InProgress
+                        || proposal.status == ProposalStatus::Delay
+                    {
+                        // Uncontested proposal passes with lower threshold after the grace period.
+                        match policy.optimistic_grace_period(&proposal, &total_supply) {
+                            Some(grace_period) => {
+                                if proposal.status == ProposalStatus::InProgress {
+                                    proposal.status = ProposalStatus::Delay;
+                                    proposal.grace_period_end = Some(WrappedTimestamp::from(
+                                        env::block_timestamp().saturating_add(grace_period),
+                                    ));
+                                }
+                                if env::block_timestamp() >= proposal.grace_period_end.unwrap().0 {
+                                    proposal.status = ProposalStatus::Approved;
+                                }
+                            }
+                            None => {
+                                proposal.status = ProposalStatus::InProgress;
+                                proposal.grace_period_end = None;
+                            }
+                        }
+                    }
+                }
                 if proposal.status == ProposalStatus::Approved {
-                    self.internal_execute_proposal(&policy, &proposal);
+                    if let ProposalKind::ActionPoll { .. } = proposal.kind {
+                        proposal.winning_option =
+                            policy.action_poll_winner(&proposal, &total_supply, &payout);
+                    }
+                    self.internal_approve_proposal(&policy, &mut proposal, id);
                     true
                 } else if proposal.status == ProposalStatus::Removed {
-                    self.internal_reject_proposal(&policy, &proposal, false);
+                    self.internal_reject_proposal(id, &proposal, false);
                     self.proposals.remove(&id);
                     false
                 } else if proposal.status == ProposalStatus::Rejected {
-                    self.internal_reject_proposal(&policy, &proposal, true);
+                    self.internal_reject_proposal(id, &proposal, true);
                     true
                 } else {
                     // Still in progress or expired.
                     true
                 }
             }
+            Action::Finalize if proposal.status == ProposalStatus::Delay => {
+                assert!(
+                    env::block_timestamp() >= proposal.grace_period_end.unwrap().0,
+                    "ERR_GRACE_PERIOD_NOT_OVER"
+                );
+                self.internal_approve_proposal(&policy, &mut proposal, id);
+                true
+            }
             Action::Finalize => {
-                proposal.status = policy.proposal_status(
-                    &proposal,
-                    policy.roles.iter().map(|r| r.name.clone()).collect(),
-                    self.total_delegation_amount,
+                assert_eq!(
+                    proposal.status,
+                    ProposalStatus::InProgress,
+                    "ERR_PROPOSAL_NOT_IN_PROGRESS"
                 );
+                proposal.status = if policy.is_proposal_expired(&proposal) {
+                    policy.expired_proposal_status(&proposal, &total_supply, &payout)
+                } else if policy.is_approval_unreachable(&proposal, &total_supply, &payout) {
+                    // Outcome is already decided, close the proposal early.
+                    ProposalStatus::Rejected
+                } else {
+                    env::panic(b"ERR_PROPOSAL_NOT_EXPIRED")
+                };
+                if proposal.status == ProposalStatus::Approved {
+                    // Not vetoed during the voting period or poll has a winner.
+                    if let ProposalKind::ActionPoll { .. } = proposal.kind {
+                        proposal.winning_option =
+                            policy.action_poll_winner(&proposal, &total_supply, &payout);
+                    }
+                    self.internal_approve_proposal(&policy, &mut proposal, id);
+                } else {
+                    self.internal_reject_proposal(id, &proposal, true);
+                }
+                true
+            }
+            // Moving takes the DAO's funds for the bond in the hub, so it must be voted on.
+            Action::MoveToHub => env::panic(b"ERR_MOVE_TO_HUB_NEEDS_PROPOSAL"),
+            Action::Retry => {
                 assert_eq!(
                     proposal.status,
-                    ProposalStatus::Expired,
-                    "ERR_PROPOSAL_NOT_EXPIRED"
+                    ProposalStatus::Failed,
+                    "ERR_PROPOSAL_NOT_FAILED"
+                );
+                // Proposal that failed to reserve tokens on approval still waits for its execution delay.
+                assert!(
+                    env::block_timestamp()
+                        >= proposal.executable_at.map_or(0, |timestamp| timestamp.0),
+                    "ERR_PROPOSAL_NOT_EXECUTABLE_YET"
+                );
+                assert!(
+                    self.internal_reserve_for_proposal(proposal.executed_kind()),
+                    "ERR_NOT_ENOUGH_TOKENS"
                 );
-                self.internal_reject_proposal(&policy, &proposal, true);
+                proposal.status = ProposalStatus::Approved;
+                self.internal_execute_proposal(&policy, &proposal, id);
                 true
             }
-            Action::MoveToHub => false,
         };
         if update {
             self.proposals
-                .insert(&id, &VersionedProposal::Default(proposal));
+                .insert(&id, &VersionedProposal::Current(proposal));
         }
         if let Some(memo) = memo {
             log!("Memo: {}", memo);
         }
     }
 }
+
+#[near_bindgen]
+impl Contract {
+    /// Add proposal to this DAO.
+    #[payable]
+    pub fn add_proposal(&mut self, proposal: ProposalInput) -> u64 {
+        // Validate bond attached.
+        let policy = self.policy.get().unwrap().to_policy();
+        assert_eq!(
+            policy.proposal_bond_token, BASE_TOKEN,
+            "ERR_WRONG_BOND_TOKEN"
+        );
+        assert!(
+            env::attached_deposit() >= policy.get_proposal_bond(&proposal.kind),
+            "ERR_MIN_BOND"
+        );
+        self.internal_add_proposal(
+            env::predecessor_account_id(),
+            proposal,
+            &policy,
+            HashMap::default(),
+        )
+    }
+
+    /// Act on given proposal by id, if permissions allow.
+    /// Memo is logged but not stored in the state. Can be used to leave notes or explain the action.
+    pub fn act_proposal(&mut self, id: u64, action: Action, memo: Option<String>) {
+        self.internal_act_proposal(
+            env::predecessor_account_id(),
+            HashMap::default(),
+            id,
+            action,
+            memo,
+        );
+    }
+
+    /// Execute queued proposal after its execution delay has passed.
+    /// Can be called by anyone.
+    pub fn execute_proposal(&mut self, id: u64) -> PromiseOrValue<()> {
+        let mut proposal: Proposal = self.internal_get_proposal(id).expect("ERR_NO_PROPOSAL");
+        assert_eq!(
+            proposal.status,
+            ProposalStatus::Queued,
+            "ERR_PROPOSAL_NOT_QUEUED"
+        );
+        assert!(
+            env::block_timestamp() >= proposal.executable_at.expect("ERR_NO_EXECUTION_TIME").0,
+            "ERR_PROPOSAL_NOT_EXECUTABLE_YET"
+        );
+        let policy = self.policy.get().unwrap().to_policy();
+        proposal.status = ProposalStatus::Approved;
+        let result = self.internal_execute_proposal(&policy, &proposal, id);
+        self.proposals
+            .insert(&id, &VersionedProposal::Current(proposal));
+        result
+    }
+
+    /// Verifies result of the proposal execution.
+    /// Returns the bond on success, otherwise marks proposal as `Failed` so it can be retried.
+    #[private]
+    pub fn on_proposal_callback(&mut self, proposal_id: u64) -> PromiseOrValue<()> {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "ERR_UNEXPECTED_CALLBACK_PROMISES"
+        );
+        let mut proposal: Proposal = self
+            .internal_get_proposal(proposal_id)
+            .expect("ERR_NO_PROPOSAL");
+        let result = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {
+                if let ProposalKind::Transfer {
+                    token_id, amount, ..
+                } = proposal.executed_kind()
+                {
+                    self.internal_withdraw(token_id, amount.0);
+                }
+                self.internal_return_bond(proposal_id, &proposal)
+            }
+            PromiseResult::Failed => {
+                // Tokens are reserved again on retry.
+                self.internal_release_for_proposal(proposal.executed_kind());
+                proposal.status = ProposalStatus::Failed;
+                PromiseOrValue::Value(())
+            }
+        };
+        self.proposals
+            .insert(&proposal_id, &VersionedProposal::Current(proposal));
+        result
+    }
+
+    /// Verifies result of the given step of the multi function call.
+    /// On success executes next step or returns the bond if it was the last one.
+    /// On failure marks proposal as `Failed` and records the step to resume from on retry.
+    #[private]
+    pub fn on_call_step_callback(&mut self, proposal_id: u64, step: u64) -> PromiseOrValue<()> {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "ERR_UNEXPECTED_CALLBACK_PROMISES"
+        );
+        let mut proposal: Proposal = self
+            .internal_get_proposal(proposal_id)
+            .expect("ERR_NO_PROPOSAL");
+        let result = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => {
+                proposal.failed_step = None;
+                let next_step = step as usize + 1;
+                match proposal.executed_kind() {
+                    ProposalKind::MultiFunctionCall { calls } if next_step < calls.len() => {
+                        self.internal_execute_call_step(proposal_id, calls, next_step)
+                    }
+                    _ => self.internal_return_bond(proposal_id, &proposal),
+                }
+            }
+            PromiseResult::Failed => {
+                proposal.status = ProposalStatus::Failed;
+                proposal.failed_step = Some(step);
+                PromiseOrValue::Value(())
+            }
+        };
+        self.proposals
+            .insert(&proposal_id, &VersionedProposal::Current(proposal));
+        result
+    }
+
+    /// Adds the moved proposal in the hub with the bond required by the hub's policy, paid by this DAO.
+    /// Bond is paid from $NEAR that isn't locked, so not from storage, bonds of other proposals or reserved payouts.
+    /// Returns proposal back to its previous status if the policy can't be read or the bond can't be paid.
+    #[private]
+    pub fn on_hub_policy_callback(
+        &mut self,
+        proposal_id: u64,
+        hub_id: AccountId,
+    ) -> PromiseOrValue<()> {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "ERR_UNEXPECTED_CALLBACK_PROMISES"
+        );
+        let mut proposal: Proposal = self
+            .internal_get_proposal(proposal_id)
+            .expect("ERR_NO_PROPOSAL");
+        let bond = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => serde_json::from_slice::<HubPolicy>(&value)
+                .ok()
+                .and_then(|hub_policy| hub_policy.get_proposal_bond(&proposal.kind)),
+            PromiseResult::Failed => None,
+        };
+        match bond {
+            Some(bond) if bond <= env::account_balance().saturating_sub(self.locked_amount) => {
+                Promise::new(hub_id.clone())
+                    .function_call(
+                        b"add_proposal".to_vec(),
+                        json!({
+                            "proposal": {
+                                "description": proposal.description,
+                                "kind": proposal.kind,
+                            }
+                        })
+                        .to_string()
+                        .into_bytes(),
+                        bond,
+                        GAS_FOR_MOVE_PROPOSAL,
+                    )
+                    .then(ext_self::on_move_proposal_callback(
+                        proposal_id,
+                        hub_id,
+                        &env::current_account_id(),
+                        0,
+                        GAS_FOR_PROPOSAL_CALLBACK,
+                    ))
+                    .into()
+            }
+            _ => {
+                env::log(b"ERR_HUB_BOND");
+                proposal.status = proposal.status_before_move();
+                self.proposals
+                    .insert(&proposal_id, &VersionedProposal::Current(proposal));
+                PromiseOrValue::Value(())
+            }
+        }
+    }
+
+    /// Records id of the proposal in the hub and returns the bond to the proposer
+    /// if it was added successfully. The hub refunds its bond to this DAO, as the proposer there.
+    /// Otherwise returns proposal back to its previous status, bond attached for the hub comes back to this contract.
+    #[private]
+    pub fn on_move_proposal_callback(&mut self, proposal_id: u64, hub_id: AccountId) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "ERR_UNEXPECTED_CALLBACK_PROMISES"
+        );
+        let mut proposal: Proposal = self
+            .internal_get_proposal(proposal_id)
+            .expect("ERR_NO_PROPOSAL");
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => {
+                let remote_id: u64 =
+                    serde_json::from_slice(&value).expect("ERR_WRONG_VAL_RECEIVED");
+                proposal.moved_to = Some((hub_id, remote_id));
+                self.internal_return_bond(proposal_id, &proposal);
+            }
+            PromiseResult::Failed => {
+                proposal.status = proposal.status_before_move();
+            }
+        };
+        self.proposals
+            .insert(&proposal_id, &VersionedProposal::Current(proposal));
+    }
+}
@@ -0,0 +1,339 @@
+use std::cmp::min;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{WrappedDuration, WrappedTimestamp, U128};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, PromiseOrValue, PromiseResult, Timestamp};
+
+use crate::proposals::ext_self;
+use crate::types::GAS_FOR_RECURRING_TRANSFER_CALLBACK;
+use crate::*;
+
+/// Schedule of fixed transfers, paid once per period.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct RecurringTransfer {
+    /// Token to pay in. Can be "" for $NEAR or a valid account id.
+    pub token_id: AccountId,
+    /// Account receiving the payments.
+    pub receiver_id: AccountId,
+    /// Amount paid each period.
+    pub amount: U128,
+    /// Time when the first period is due.
+    pub start: WrappedTimestamp,
+    /// Time between payments.
+    pub period: WrappedDuration,
+    /// Total number of periods.
+    pub times: u32,
+    /// Number of periods already paid.
+    pub paid_periods: u32,
+}
+
+impl RecurringTransfer {
+    /// Returns number of periods that are due by given time, including paid ones.
+    pub fn due_periods(&self, timestamp: Timestamp) -> u32 {
+        if timestamp < self.start.0 {
+            0
+        } else {
+            min(
+                self.times as u64,
+                (timestamp - self.start.0) / self.period.0 + 1,
+            ) as u32
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Clone, Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub enum VersionedRecurringTransfer {
+    Default(RecurringTransfer),
+}
+
+impl From<VersionedRecurringTransfer> for RecurringTransfer {
+    fn from(v: VersionedRecurringTransfer) -> Self {
+        match v {
+            VersionedRecurringTransfer::Default(r) => r,
+        }
+    }
+}
+
+impl Contract {
+    /// Adds recurring transfer to the storage and returns it's id.
+    pub(crate) fn internal_add_recurring_transfer(
+        &mut self,
+        recurring_transfer: &RecurringTransfer,
+    ) -> u64 {
+        let id = self.last_recurring_transfer_id;
+        self.recurring_transfers.insert(
+            &id,
+            &VersionedRecurringTransfer::Default(recurring_transfer.clone()),
+        );
+        self.last_recurring_transfer_id += 1;
+        id
+    }
+
+    /// Stops given recurring transfer at the current time. Periods due so far can still be triggered,
+    /// amount of the rest is released back to the treasury. Does nothing if all periods are already due.
+    pub(crate) fn internal_cancel_recurring_transfer(&mut self, id: u64) {
+        let mut recurring_transfer: RecurringTransfer = self
+            .recurring_transfers
+            .get(&id)
+            .expect("ERR_NO_RECURRING_TRANSFER")
+            .into();
+        let times = std::cmp::max(
+            recurring_transfer.paid_periods,
+            recurring_transfer.due_periods(env::block_timestamp()),
+        );
+        if times >= recurring_transfer.times {
+            return;
+        }
+        self.internal_release(
+            &recurring_transfer.token_id,
+            recurring_transfer.amount.0 * (recurring_transfer.times - times) as u128,
+        );
+        recurring_transfer.times = times;
+        self.recurring_transfers.insert(
+            &id,
+            &VersionedRecurringTransfer::Default(recurring_transfer),
+        );
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Pays all periods of given recurring transfer that are due and not paid yet.
+    /// Can be called by anyone.
+    pub fn trigger_recurring(&mut self, id: u64) -> PromiseOrValue<()> {
+        let mut recurring_transfer: RecurringTransfer = self
+            .recurring_transfers
+            .get(&id)
+            .expect("ERR_NO_RECURRING_TRANSFER")
+            .into();
+        let due_periods = recurring_transfer.due_periods(env::block_timestamp());
+        assert!(
+            due_periods > recurring_transfer.paid_periods,
+            "ERR_NO_PAYMENT_DUE"
+        );
+        let periods = due_periods - recurring_transfer.paid_periods;
+        let amount = recurring_transfer
+            .amount
+            .0
+            .checked_mul(periods as u128)
+            .expect("ERR_AMOUNT_OVERFLOW");
+        recurring_transfer.paid_periods = due_periods;
+        self.recurring_transfers.insert(
+            &id,
+            &VersionedRecurringTransfer::Default(recurring_transfer.clone()),
+        );
+        match self.internal_payout(
+            &recurring_transfer.token_id,
+            &recurring_transfer.receiver_id,
+            amount,
+            format!("Recurring transfer {} payment", id),
+            None,
+        ) {
+            PromiseOrValue::Promise(promise) => promise
+                .then(ext_self::on_recurring_transfer_callback(
+                    id,
+                    periods,
+                    &env::current_account_id(),
+                    0,
+                    GAS_FOR_RECURRING_TRANSFER_CALLBACK,
+                ))
+                .into(),
+            value => value,
+        }
+    }
+
+    /// Verifies the payment of given number of periods. On failure they can be triggered again.
+    #[private]
+    pub fn on_recurring_transfer_callback(&mut self, recurring_transfer_id: u64, periods: u32) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "ERR_UNEXPECTED_CALLBACK_PROMISES"
+        );
+        let mut recurring_transfer: RecurringTransfer = self
+            .recurring_transfers
+            .get(&recurring_transfer_id)
+            .expect("ERR_NO_RECURRING_TRANSFER")
+            .into();
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => self.internal_withdraw(
+                &recurring_transfer.token_id,
+                recurring_transfer
+                    .amount
+                    .0
+                    .checked_mul(periods as u128)
+                    .expect("ERR_AMOUNT_OVERFLOW"),
+            ),
+            PromiseResult::Failed => {
+                recurring_transfer.paid_periods -= periods;
+                self.recurring_transfers.insert(
+                    &recurring_transfer_id,
+                    &VersionedRecurringTransfer::Default(recurring_transfer),
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+    use near_sdk_sim::to_yocto;
+
+    use crate::proposals::{ProposalInput, ProposalKind, ProposalStatus};
+    use crate::types::BASE_TOKEN;
+    use crate::{Action, Config};
+
+    use super::*;
+
+    /// Adds and approves proposal for recurring transfer of given amount of $NEAR. Returns proposal id.
+    fn propose_recurring_transfer(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        amount: Balance,
+    ) -> u64 {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::RecurringTransfer {
+                token_id: BASE_TOKEN.to_string(),
+                receiver_id: accounts(2),
+                amount: U128(amount),
+                start: WrappedTimestamp::from(100),
+                period: WrappedDuration::from(100),
+                times: 3,
+            },
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+        id
+    }
+
+    fn add_recurring_transfer(context: &mut VMContextBuilder, contract: &mut Contract) -> u64 {
+        propose_recurring_transfer(context, contract, 500);
+        contract.get_last_recurring_transfer_id() - 1
+    }
+
+    #[test]
+    fn test_recurring_transfer() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        let id = add_recurring_transfer(&mut context, &mut contract);
+        assert_eq!(contract.get_upcoming_payments(0, 10)[0].due_time.0, 100);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(3))
+            .block_timestamp(250)
+            .build());
+        contract.trigger_recurring(id);
+        assert_eq!(
+            contract
+                .get_recurring_transfer(id)
+                .recurring_transfer
+                .paid_periods,
+            2
+        );
+        let upcoming = contract.get_upcoming_payments(0, 10);
+        assert_eq!(upcoming[0].due_time.0, 300);
+        assert_eq!(upcoming[0].remaining_periods, 1);
+
+        testing_env!(context.block_timestamp(1_000).build());
+        contract.trigger_recurring(id);
+        assert_eq!(
+            contract
+                .get_recurring_transfer(id)
+                .recurring_transfer
+                .paid_periods,
+            3
+        );
+        assert!(contract.get_upcoming_payments(0, 10).is_empty());
+    }
+
+    #[test]
+    fn test_recurring_transfer_not_enough_near() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        let available = contract.get_available_amount().0;
+        let id = propose_recurring_transfer(&mut context, &mut contract, available / 2);
+        assert_eq!(
+            contract.get_proposal(id).proposal.status,
+            ProposalStatus::Failed
+        );
+        assert_eq!(contract.get_last_recurring_transfer_id(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_RECURRING_TRANSFER")]
+    fn test_recurring_transfer_overflow() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        propose_recurring_transfer(&mut context, &mut contract, u128::MAX / 2);
+    }
+
+    #[test]
+    fn test_cancel_recurring_transfer() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        let available = contract.get_available_amount().0;
+        let id = add_recurring_transfer(&mut context, &mut contract);
+        assert_eq!(contract.get_available_amount().0, available - 1_500);
+
+        testing_env!(context.block_timestamp(150).build());
+        let proposal_id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::CancelRecurringTransfer {
+                recurring_transfer_id: id,
+            },
+        });
+        contract.act_proposal(proposal_id, Action::VoteApprove, None);
+        // First period is due and can still be paid, the rest is released.
+        assert_eq!(contract.get_available_amount().0, available - 500);
+        assert_eq!(
+            contract.get_recurring_transfer(id).recurring_transfer.times,
+            1
+        );
+        testing_env!(context.block_timestamp(1_000).build());
+        contract.trigger_recurring(id);
+        assert!(contract.get_upcoming_payments(0, 10).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_PAYMENT_DUE")]
+    fn test_recurring_transfer_not_due() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        let id = add_recurring_transfer(&mut context, &mut contract);
+        testing_env!(context.block_timestamp(150).build());
+        contract.trigger_recurring(id);
+        contract.trigger_recurring(id);
+    }
+}
@@ -0,0 +1,337 @@
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{WrappedTimestamp, U128};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{env, near_bindgen, AccountId, Balance, PromiseOrValue, PromiseResult, Timestamp};
+
+use crate::delegation::mul_div;
+use crate::proposals::ext_self;
+use crate::types::GAS_FOR_STREAM_WITHDRAW_CALLBACK;
+use crate::*;
+
+/// Payment that vests linearly over time and is withdrawn by the receiver.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Stream {
+    /// Token the stream is paid out in. Can be "" for $NEAR or a valid account id.
+    pub token_id: AccountId,
+    /// Account that can withdraw vested amount.
+    pub receiver_id: AccountId,
+    /// Total amount to be paid out by the end of the stream.
+    pub amount: U128,
+    /// Time when vesting starts.
+    pub start: WrappedTimestamp,
+    /// Time when full amount is vested.
+    pub end: WrappedTimestamp,
+    /// Nothing can be withdrawn before this time, after it amount vested since the start is available.
+    pub cliff: Option<WrappedTimestamp>,
+    /// Amount already withdrawn.
+    pub withdrawn: U128,
+}
+
+impl Stream {
+    /// Returns amount vested by given time.
+    pub fn vested_amount(&self, timestamp: Timestamp) -> Balance {
+        let cliff = self
+            .cliff
+            .as_ref()
+            .map(|cliff| cliff.0)
+            .unwrap_or(self.start.0);
+        if timestamp < cliff {
+            0
+        } else if timestamp >= self.end.0 {
+            self.amount.0
+        } else {
+            mul_div(
+                self.amount.0,
+                timestamp - self.start.0,
+                self.end.0 - self.start.0,
+            )
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Clone, Debug))]
+#[serde(crate = "near_sdk::serde")]
+pub enum VersionedStream {
+    Default(Stream),
+}
+
+impl From<VersionedStream> for Stream {
+    fn from(v: VersionedStream) -> Self {
+        match v {
+            VersionedStream::Default(s) => s,
+        }
+    }
+}
+
+impl Contract {
+    /// Adds stream to the storage and returns it's id.
+    pub(crate) fn internal_add_stream(&mut self, stream: &Stream) -> u64 {
+        let id = self.last_stream_id;
+        self.streams
+            .insert(&id, &VersionedStream::Default(stream.clone()));
+        self.last_stream_id += 1;
+        id
+    }
+
+    /// Stops given stream at the current time. Amount vested so far can still be withdrawn,
+    /// the rest is released back to the treasury. Does nothing if the stream already ended or was cancelled.
+    pub(crate) fn internal_cancel_stream(&mut self, id: u64) {
+        let mut stream: Stream = self.streams.get(&id).expect("ERR_NO_STREAM").into();
+        let now = env::block_timestamp();
+        if now >= stream.end.0 {
+            return;
+        }
+        let vested = stream.vested_amount(now);
+        self.internal_release(&stream.token_id, stream.amount.0 - vested);
+        stream.amount = U128(vested);
+        stream.start = WrappedTimestamp::from(std::cmp::min(stream.start.0, now));
+        stream.end = WrappedTimestamp::from(now);
+        stream.cliff = None;
+        self.streams.insert(&id, &VersionedStream::Default(stream));
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Withdraw amount vested so far in given stream. Can only be called by the receiver.
+    pub fn stream_withdraw(&mut self, id: u64) -> PromiseOrValue<()> {
+        let mut stream: Stream = self.streams.get(&id).expect("ERR_NO_STREAM").into();
+        assert_eq!(
+            env::predecessor_account_id(),
+            stream.receiver_id,
+            "ERR_NOT_STREAM_RECEIVER"
+        );
+        let amount = stream.vested_amount(env::block_timestamp()) - stream.withdrawn.0;
+        assert!(amount > 0, "ERR_NOTHING_TO_WITHDRAW");
+        stream.withdrawn = U128(stream.withdrawn.0 + amount);
+        self.streams
+            .insert(&id, &VersionedStream::Default(stream.clone()));
+        match self.internal_payout(
+            &stream.token_id,
+            &stream.receiver_id,
+            amount,
+            format!("Stream {} withdrawal", id),
+            None,
+        ) {
+            PromiseOrValue::Promise(promise) => promise
+                .then(ext_self::on_stream_withdraw_callback(
+                    id,
+                    U128(amount),
+                    &env::current_account_id(),
+                    0,
+                    GAS_FOR_STREAM_WITHDRAW_CALLBACK,
+                ))
+                .into(),
+            value => value,
+        }
+    }
+
+    /// Verifies the withdrawal from the stream. On failure the amount becomes available to withdraw again.
+    #[private]
+    pub fn on_stream_withdraw_callback(&mut self, stream_id: u64, amount: U128) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "ERR_UNEXPECTED_CALLBACK_PROMISES"
+        );
+        let mut stream: Stream = self.streams.get(&stream_id).expect("ERR_NO_STREAM").into();
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => self.internal_withdraw(&stream.token_id, amount.0),
+            PromiseResult::Failed => {
+                stream.withdrawn = U128(stream.withdrawn.0 - amount.0);
+                self.streams
+                    .insert(&stream_id, &VersionedStream::Default(stream));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+    use near_sdk_sim::to_yocto;
+
+    use crate::policy::{AmountThreshold, WeightOrRatio};
+    use crate::proposals::{ProposalInput, ProposalKind};
+    use crate::types::BASE_TOKEN;
+    use crate::{Action, Config};
+
+    use super::*;
+
+    fn add_stream(context: &mut VMContextBuilder, contract: &mut Contract) -> u64 {
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Stream {
+                token_id: BASE_TOKEN.to_string(),
+                receiver_id: accounts(2),
+                amount: U128(1_000),
+                start: WrappedTimestamp::from(100),
+                end: WrappedTimestamp::from(1_100),
+                cliff: Some(WrappedTimestamp::from(300)),
+            },
+        });
+        contract.act_proposal(id, Action::VoteApprove, None);
+        contract.get_last_stream_id() - 1
+    }
+
+    #[test]
+    fn test_stream_lifecycle() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        let available = contract.get_available_amount().0;
+        let id = add_stream(&mut context, &mut contract);
+        // Streamed $NEAR is reserved.
+        assert_eq!(contract.get_available_amount().0, available - 1_000);
+        let stream = contract.get_stream(id).stream;
+        assert_eq!(stream.vested_amount(200), 0);
+        assert_eq!(stream.vested_amount(300), 200);
+        assert_eq!(stream.vested_amount(600), 500);
+        assert_eq!(stream.vested_amount(2_000), 1_000);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(600)
+            .build());
+        contract.stream_withdraw(id);
+        assert_eq!(contract.get_stream(id).stream.withdrawn, U128(500));
+
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(700)
+            .build());
+        let proposal_id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::CancelStream { stream_id: id },
+        });
+        contract.act_proposal(proposal_id, Action::VoteApprove, None);
+        assert_eq!(contract.get_stream(id).stream.amount, U128(600));
+        assert_eq!(contract.get_available_amount().0, available - 600);
+
+        testing_env!(context
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(2_000)
+            .build());
+        contract.stream_withdraw(id);
+        assert_eq!(contract.get_stream(id).stream.withdrawn, U128(600));
+    }
+
+    #[test]
+    fn test_stream_amount_threshold() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![
+            accounts(1).into(),
+            accounts(2).into(),
+            accounts(3).into(),
+        ])
+        .upgrade();
+        // Single vote is enough for streams up to 1000.
+        policy
+            .to_policy_mut()
+            .default_vote_policy
+            .amount_thresholds
+            .insert(
+                BASE_TOKEN.to_string(),
+                vec![AmountThreshold {
+                    max_amount: U128(1_000),
+                    threshold: WeightOrRatio::Weight(U128(1)),
+                }],
+            );
+        let mut contract = Contract::new(Config::test_config(), policy);
+        let id = add_stream(&mut context, &mut contract);
+        assert_eq!(contract.get_stream(id).stream.amount, U128(1_000));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_STREAM_ENDED")]
+    fn test_cancel_stream_twice() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        let id = add_stream(&mut context, &mut contract);
+        testing_env!(context.block_timestamp(700).build());
+        let proposal_id = contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::CancelStream { stream_id: id },
+        });
+        contract.act_proposal(proposal_id, Action::VoteApprove, None);
+        testing_env!(context.block_timestamp(800).build());
+        contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::CancelStream { stream_id: id },
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_INVALID_STREAM")]
+    fn test_stream_too_long() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::Stream {
+                token_id: BASE_TOKEN.to_string(),
+                receiver_id: accounts(2),
+                amount: U128(1_000),
+                start: WrappedTimestamp::from(0),
+                end: WrappedTimestamp::from(u64::MAX),
+                cliff: None,
+            },
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NO_STREAM")]
+    fn test_cancel_unknown_stream() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .attached_deposit(to_yocto("1"))
+            .build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.add_proposal(ProposalInput {
+            description: "test".to_string(),
+            kind: ProposalKind::CancelStream { stream_id: 0 },
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_STREAM_RECEIVER")]
+    fn test_stream_withdraw_not_receiver() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        let id = add_stream(&mut context, &mut contract);
+        testing_env!(context.block_timestamp(600).build());
+        contract.stream_withdraw(id);
+    }
+}
@@ -0,0 +1,578 @@
+use std::collections::HashMap;
+
+use near_contract_standards::fungible_token::core_impl::ext_fungible_token;
+use near_contract_standards::fungible_token::receiver::FungibleTokenReceiver;
+use near_sdk::json_types::{ValidAccountId, WrappedTimestamp, U128};
+use near_sdk::serde_json;
+use near_sdk::{
+    env, log, near_bindgen, AccountId, Balance, Promise, PromiseOrValue, PromiseResult,
+};
+
+use crate::proposals::ext_self;
+use crate::types::{
+    BASE_TOKEN, GAS_FOR_SPEND_CALLBACK, GAS_FOR_SYNC_TREASURY_CALLBACK, GAS_FOR_TREASURY_VIEW,
+};
+use crate::*;
+
+/// Max number of spendings within the window of the allowance.
+/// Each spend must be at least this fraction of the allowance, so the spendings stored per role and token stay bounded.
+const MAX_SPENDINGS: Balance = 100;
+
+/// Balance of the fungible token held by this DAO.
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize)]
+#[cfg_attr(not(target_arch = "wasm32"), derive(Debug, PartialEq))]
+#[serde(crate = "near_sdk::serde")]
+pub struct TokenBalance {
+    /// Total amount deposited and not yet paid out.
+    pub amount: U128,
+    /// Amount reserved for payouts of approved proposals.
+    pub reserved: U128,
+}
+
+impl Contract {
+    pub(crate) fn internal_token_balance(&self, token_id: &AccountId) -> TokenBalance {
+        self.treasury.get(token_id).unwrap_or(TokenBalance {
+            amount: U128(0),
+            reserved: U128(0),
+        })
+    }
+
+    fn internal_set_token_balance(&mut self, token_id: &AccountId, balance: &TokenBalance) {
+        if self.treasury.insert(token_id, balance).is_none() {
+            self.treasury_tokens.push(token_id);
+        }
+    }
+
+    /// Records given amount of the token deposited into the treasury.
+    pub(crate) fn internal_deposit(&mut self, token_id: &AccountId, amount: Balance) {
+        let mut balance = self.internal_token_balance(token_id);
+        balance.amount = U128(balance.amount.0 + amount);
+        self.internal_set_token_balance(token_id, &balance);
+    }
+
+    /// Reserves given amount of the token, so it can't be used by other proposals.
+    /// Returns false if not enough unreserved tokens are in the treasury.
+    /// $NEAR is reserved by locking it, so it can't be spent on other payouts or bonds either.
+    fn internal_try_reserve(&mut self, token_id: &AccountId, amount: Balance) -> bool {
+        if token_id == BASE_TOKEN {
+            if env::account_balance().saturating_sub(self.locked_amount) < amount {
+                return false;
+            }
+            self.locked_amount += amount;
+            return true;
+        }
+        let mut balance = self.internal_token_balance(token_id);
+        if balance.amount.0.saturating_sub(balance.reserved.0) < amount {
+            return false;
+        }
+        balance.reserved = U128(balance.reserved.0 + amount);
+        self.internal_set_token_balance(token_id, &balance);
+        true
+    }
+
+    /// Reserves given amount of the token. Fails if not enough unreserved tokens are in the treasury.
+    pub(crate) fn internal_reserve(&mut self, token_id: &AccountId, amount: Balance) {
+        assert!(
+            self.internal_try_reserve(token_id, amount),
+            "ERR_NOT_ENOUGH_TOKENS"
+        );
+    }
+
+    /// Releases given amount of the token reserved for a payout that won't happen.
+    pub(crate) fn internal_release(&mut self, token_id: &AccountId, amount: Balance) {
+        if token_id == BASE_TOKEN {
+            self.locked_amount = self.locked_amount.saturating_sub(amount);
+            return;
+        }
+        let mut balance = self.internal_token_balance(token_id);
+        balance.reserved = U128(balance.reserved.0.saturating_sub(amount));
+        self.internal_set_token_balance(token_id, &balance);
+    }
+
+    /// Records given amount of the token paid out of the reserved amount.
+    /// Saturates, as tokens may have been paid out before they were tracked.
+    /// $NEAR already left the account balance, so it's only unlocked.
+    pub(crate) fn internal_withdraw(&mut self, token_id: &AccountId, amount: Balance) {
+        if token_id == BASE_TOKEN {
+            self.locked_amount = self.locked_amount.saturating_sub(amount);
+            return;
+        }
+        let mut balance = self.internal_token_balance(token_id);
+        balance.amount = U128(balance.amount.0.saturating_sub(amount));
+        balance.reserved = U128(balance.reserved.0.saturating_sub(amount));
+        self.internal_set_token_balance(token_id, &balance);
+    }
+
+    /// Returns token and amount that given proposal kind is going to pay out from the treasury.
+    fn internal_reservation(kind: &ProposalKind) -> Option<(&AccountId, Balance)> {
+        match kind {
+            ProposalKind::Transfer {
+                token_id, amount, ..
+            }
+            | ProposalKind::Stream {
+                token_id, amount, ..
+            } => Some((token_id, amount.0)),
+            // Amount too large to reserve saturates, so the reservation fails.
+            ProposalKind::AddBounty { bounty } => Some((
+                &bounty.token,
+                bounty.amount.0.saturating_mul(bounty.times as u128),
+            )),
+            ProposalKind::RecurringTransfer {
+                token_id,
+                amount,
+                times,
+                ..
+            } => Some((token_id, amount.0.saturating_mul(*times as u128))),
+            _ => None,
+        }
+    }
+
+    /// Reserves tokens that given approved proposal is going to pay out.
+    /// Returns false if there are not enough tokens, then nothing is reserved.
+    pub(crate) fn internal_reserve_for_proposal(&mut self, kind: &ProposalKind) -> bool {
+        match Self::internal_reservation(kind) {
+            Some((token_id, amount)) => self.internal_try_reserve(token_id, amount),
+            None => true,
+        }
+    }
+
+    /// Releases tokens reserved for given proposal that is not going to pay out.
+    pub(crate) fn internal_release_for_proposal(&mut self, kind: &ProposalKind) {
+        if let Some((token_id, amount)) = Self::internal_reservation(kind) {
+            self.internal_release(token_id, amount);
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Reads balance of given token held by this DAO into the treasury, e.g. for tokens sent with plain `ft_transfer`.
+    /// Starts tracking the token, deposits of untracked tokens are not recorded.
+    /// Can be called by members of any role except everyone.
+    /// Balance is read from the token, so should be synced when no payouts in it are pending.
+    pub fn sync_treasury(&mut self, token_id: ValidAccountId) -> Promise {
+        let policy = self.policy.get().unwrap().to_policy();
+        assert!(
+            policy
+                .get_user_roles(self.internal_user_info(&policy, &env::predecessor_account_id()))
+                .iter()
+                .any(|role| !matches!(role.kind, RoleKind::Everyone)),
+            "ERR_PERMISSION_DENIED"
+        );
+        ext_fungible_token::ft_balance_of(
+            env::current_account_id(),
+            token_id.as_ref(),
+            0,
+            GAS_FOR_TREASURY_VIEW,
+        )
+        .then(ext_self::on_sync_treasury_callback(
+            token_id.into(),
+            &env::current_account_id(),
+            0,
+            GAS_FOR_SYNC_TREASURY_CALLBACK,
+        ))
+    }
+
+    /// Records balance of given token returned by the view call in the treasury. Reserved amount is kept.
+    #[private]
+    pub fn on_sync_treasury_callback(&mut self, token_id: AccountId) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "ERR_UNEXPECTED_CALLBACK_PROMISES"
+        );
+        let amount = match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(value) => {
+                serde_json::from_slice::<U128>(&value).expect("ERR_WRONG_BALANCE")
+            }
+            PromiseResult::Failed => env::panic(b"ERR_BALANCE_VIEW_FAILED"),
+        };
+        let mut balance = self.internal_token_balance(&token_id);
+        balance.amount = amount;
+        self.internal_set_token_balance(&token_id, &balance);
+    }
+
+    /// Transfers given amount of the token without a proposal, within the spending allowance of caller's role.
+    /// Fails if none of caller's roles has enough allowance left, then `Transfer` proposal must be used instead.
+    /// Amount must be at least `1 / MAX_SPENDINGS` of the allowance (and not zero) to count towards it.
+    pub fn spend(
+        &mut self,
+        token_id: AccountId,
+        receiver_id: ValidAccountId,
+        amount: U128,
+        memo: String,
+    ) -> PromiseOrValue<()> {
+        let policy = self.policy.get().unwrap().to_policy();
+        let now = env::block_timestamp();
+        let (role, mut spendings) = policy
+            .get_user_roles(self.internal_user_info(&policy, &env::predecessor_account_id()))
+            .into_iter()
+            .find_map(|role| {
+                let allowance = role.allowances.get(&token_id)?;
+                if amount.0 == 0 || amount.0 < allowance.amount.0 / MAX_SPENDINGS {
+                    return None;
+                }
+                let mut spendings = self
+                    .spendings
+                    .get(&(role.name.clone(), token_id.clone()))
+                    .unwrap_or_default();
+                // Spendings older than the window don't count towards the allowance anymore.
+                spendings.retain(|(time, _)| now - *time < allowance.window.0);
+                let spent: Balance = spendings.iter().map(|(_, spent)| spent).sum();
+                if spent + amount.0 <= allowance.amount.0 {
+                    Some((role.name.clone(), spendings))
+                } else {
+                    None
+                }
+            })
+            .expect("ERR_ALLOWANCE_EXCEEDED");
+        spendings.push((now, amount.0));
+        self.spendings
+            .insert(&(role.clone(), token_id.clone()), &spendings);
+        self.internal_reserve(&token_id, amount.0);
+        match self.internal_payout(&token_id, receiver_id.as_ref(), amount.0, memo, None) {
+            PromiseOrValue::Promise(promise) => promise
+                .then(ext_self::on_spend_callback(
+                    role,
+                    token_id,
+                    amount,
+                    WrappedTimestamp::from(now),
+                    &env::current_account_id(),
+                    0,
+                    GAS_FOR_SPEND_CALLBACK,
+                ))
+                .into(),
+            value => value,
+        }
+    }
+
+    /// Records the transfer made via `spend` in the treasury.
+    /// If it failed, releases the tokens and removes the spending from the allowance window of given role.
+    #[private]
+    pub fn on_spend_callback(
+        &mut self,
+        role: String,
+        token_id: AccountId,
+        amount: U128,
+        time: WrappedTimestamp,
+    ) {
+        assert_eq!(
+            env::promise_results_count(),
+            1,
+            "ERR_UNEXPECTED_CALLBACK_PROMISES"
+        );
+        match env::promise_result(0) {
+            PromiseResult::NotReady => unreachable!(),
+            PromiseResult::Successful(_) => self.internal_withdraw(&token_id, amount.0),
+            PromiseResult::Failed => {
+                self.internal_release(&token_id, amount.0);
+                let key = (role, token_id);
+                let mut spendings = self.spendings.get(&key).unwrap_or_default();
+                if let Some(index) = spendings
+                    .iter()
+                    .position(|spending| *spending == (time.0, amount.0))
+                {
+                    spendings.remove(index);
+                    self.spendings.insert(&key, &spendings);
+                }
+            }
+        }
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenReceiver for Contract {
+    /// Tokens transferred with an empty `msg` are deposited into the treasury, if the token is tracked.
+    /// Untracked tokens are still accepted, `sync_treasury` records them. Otherwise anyone could grow the storage.
+    /// Otherwise adds proposal given in `msg` as JSON of `ProposalInput`, with the bond paid in transferred tokens.
+    /// Tokens must be the `proposal_bond_token` of the policy. Amount above the bond is refunded.
+    fn ft_on_transfer(
+        &mut self,
+        sender_id: ValidAccountId,
+        amount: U128,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        let token_id = env::predecessor_account_id();
+        if msg.is_empty() {
+            if self.treasury.get(&token_id).is_some() {
+                self.internal_deposit(&token_id, amount.0);
+            } else {
+                log!(
+                    "Token {} is not tracked, use sync_treasury to record it",
+                    token_id
+                );
+            }
+            return PromiseOrValue::Value(U128(0));
+        }
+        let policy = self.policy.get().unwrap().to_policy();
+        assert_eq!(token_id, policy.proposal_bond_token, "ERR_WRONG_BOND_TOKEN");
+        let proposal: ProposalInput = serde_json::from_str(&msg).expect("ERR_WRONG_MSG");
+        let bond = policy.get_proposal_bond(&proposal.kind);
+        assert!(amount.0 >= bond, "ERR_MIN_BOND");
+        self.internal_add_proposal(sender_id.into(), proposal, &policy, HashMap::default());
+        PromiseOrValue::Value(U128(amount.0 - bond))
+    }
+}
+
+#[cfg(test)]
+impl Contract {
+    /// Starts tracking given token, as `sync_treasury` does while the DAO holds none of it.
+    pub(crate) fn test_track_token(&mut self, token_id: &AccountId) {
+        let balance = self.internal_token_balance(token_id);
+        self.internal_set_token_balance(token_id, &balance);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::json_types::WrappedDuration;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, MockedBlockchain};
+    use near_sdk_sim::to_yocto;
+
+    use super::*;
+
+    #[test]
+    fn test_treasury() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        let token_id: AccountId = accounts(3).into();
+        contract.test_track_token(&token_id);
+        contract.ft_on_transfer(accounts(1), U128(100), "".to_string());
+        contract.ft_on_transfer(accounts(2), U128(50), "".to_string());
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(150));
+        contract.internal_reserve(&token_id, 120);
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(30));
+        contract.internal_withdraw(&token_id, 100);
+        assert_eq!(
+            contract.get_treasury(0, 10),
+            vec![(
+                token_id,
+                TokenBalance {
+                    amount: U128(50),
+                    reserved: U128(20),
+                }
+            )]
+        );
+        assert_eq!(contract.get_available_token_amount(accounts(4)), U128(0));
+    }
+
+    #[test]
+    fn test_sync_treasury() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        // Deposit of untracked token is accepted, but not recorded.
+        contract.ft_on_transfer(accounts(1), U128(100), "".to_string());
+        assert!(contract.get_treasury(0, 10).is_empty());
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.sync_treasury(accounts(3));
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .current_account_id(accounts(0))
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Successful(
+                serde_json::to_vec(&U128(150)).unwrap()
+            )]
+        );
+        contract.on_sync_treasury_callback(accounts(3).into());
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(150));
+        assert_eq!(contract.get_treasury(0, 10).len(), 1);
+        assert!(contract.get_treasury(1, 10).is_empty());
+        // Tracked from now on.
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.ft_on_transfer(accounts(1), U128(50), "".to_string());
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(200));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_PERMISSION_DENIED")]
+    fn test_sync_treasury_not_member() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.sync_treasury(accounts(3));
+    }
+
+    fn setup_allowance(context: &mut VMContextBuilder) -> Contract {
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let mut policy = VersionedPolicy::Default(vec![accounts(1).into()]).upgrade();
+        policy.to_policy_mut().roles[1].allowances.insert(
+            accounts(3).into(),
+            SpendingAllowance {
+                amount: U128(100),
+                window: WrappedDuration::from(1_000),
+            },
+        );
+        let mut contract = Contract::new(Config::test_config(), policy);
+        contract.test_track_token(&accounts(3).into());
+        contract.ft_on_transfer(accounts(1), U128(500), "".to_string());
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.spend(
+            accounts(3).into(),
+            accounts(2),
+            U128(60),
+            "test".to_string(),
+        );
+        contract
+    }
+
+    #[test]
+    fn test_spend() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = setup_allowance(&mut context);
+        // First spending is out of the window.
+        testing_env!(context.block_timestamp(1_000).build());
+        contract.spend(
+            accounts(3).into(),
+            accounts(2),
+            U128(100),
+            "test".to_string(),
+        );
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(340));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_ALLOWANCE_EXCEEDED")]
+    fn test_spend_over_allowance() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = setup_allowance(&mut context);
+        testing_env!(context.block_timestamp(500).build());
+        contract.spend(
+            accounts(3).into(),
+            accounts(2),
+            U128(50),
+            "test".to_string(),
+        );
+    }
+
+    #[test]
+    fn test_spend_failed() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = setup_allowance(&mut context);
+        testing_env!(
+            context
+                .predecessor_account_id(accounts(0))
+                .current_account_id(accounts(0))
+                .build(),
+            Default::default(),
+            Default::default(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.on_spend_callback(
+            "council".to_string(),
+            accounts(3).into(),
+            U128(60),
+            WrappedTimestamp::from(0),
+        );
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(500));
+        // Failed spending doesn't count towards the allowance.
+        testing_env!(context
+            .predecessor_account_id(accounts(1))
+            .block_timestamp(500)
+            .build());
+        contract.spend(
+            accounts(3).into(),
+            accounts(2),
+            U128(100),
+            "test".to_string(),
+        );
+        assert_eq!(contract.get_available_token_amount(accounts(3)), U128(400));
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ENOUGH_TOKENS")]
+    fn test_spend_near_locked() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![accounts(1).into()]).upgrade();
+        policy.to_policy_mut().roles[1].allowances.insert(
+            BASE_TOKEN.to_string(),
+            SpendingAllowance {
+                amount: U128(to_yocto("1000")),
+                window: WrappedDuration::from(1_000),
+            },
+        );
+        let mut contract = Contract::new(Config::test_config(), policy);
+        let available = contract.get_available_amount();
+        contract.spend(
+            BASE_TOKEN.to_string(),
+            accounts(2),
+            U128(available.0 + 1),
+            "test".to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_ALLOWANCE_EXCEEDED")]
+    fn test_spend_too_small() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        let mut policy = VersionedPolicy::Default(vec![accounts(1).into()]).upgrade();
+        policy.to_policy_mut().roles[1].allowances.insert(
+            BASE_TOKEN.to_string(),
+            SpendingAllowance {
+                amount: U128(to_yocto("10")),
+                window: WrappedDuration::from(1_000),
+            },
+        );
+        let mut contract = Contract::new(Config::test_config(), policy);
+        // Enough to count towards the allowance.
+        contract.spend(
+            BASE_TOKEN.to_string(),
+            accounts(2),
+            U128(to_yocto("0.1")),
+            "test".to_string(),
+        );
+        contract.spend(
+            BASE_TOKEN.to_string(),
+            accounts(2),
+            U128(to_yocto("0.1") - 1),
+            "test".to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_ALLOWANCE_EXCEEDED")]
+    fn test_spend_without_allowance() {
+        let mut context = VMContextBuilder::new();
+        let mut contract = setup_allowance(&mut context);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.spend(
+            accounts(3).into(),
+            accounts(2),
+            U128(10),
+            "test".to_string(),
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "ERR_NOT_ENOUGH_TOKENS")]
+    fn test_reserve_too_much() {
+        let mut context = VMContextBuilder::new();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        let mut contract = Contract::new(
+            Config::test_config(),
+            VersionedPolicy::Default(vec![accounts(1).into()]),
+        );
+        contract.test_track_token(&accounts(3).into());
+        contract.ft_on_transfer(accounts(1), U128(100), "".to_string());
+        contract.internal_reserve(&accounts(3).into(), 101);
+    }
+}
@@ -4,6 +4,8 @@ use near_sdk::json_types::Base64VecU8;
 use near_sdk::serde::{Deserialize, Serialize};
 use near_sdk::{env, AccountId, Balance, Gas};
 
+use crate::proposals::Vote;
+
 const BLOCKCHAIN_INTERFACE_NOT_SET_ERR: &str = "Blockchain interface not set.";
 
 /// Account ID used for $NEAR.
@@ -20,6 +22,48 @@ pub const GAS_FOR_UPGRADE_SELF_DEPLOY: Gas = 30_000_000_000_000;
 
 pub const GAS_FOR_UPGRADE_REMOTE_DEPLOY: Gas = 10_000_000_000_000;
 
+/// Gas for verifying the result of proposal execution.
+pub const GAS_FOR_PROPOSAL_CALLBACK: Gas = 20_000_000_000_000;
+
+/// Gas for verifying a step of multi function call and scheduling the next one.
+pub const GAS_FOR_CALL_STEP_CALLBACK: Gas = 20_000_000_000_000;
+
+/// Gas for adding the moved proposal in the hub.
+pub const GAS_FOR_MOVE_PROPOSAL: Gas = 20_000_000_000_000;
+
+/// Gas for the view call reading the policy of the hub.
+pub const GAS_FOR_HUB_POLICY_VIEW: Gas = 10_000_000_000_000;
+
+/// Gas for adding the moved proposal in the hub with its bond, including the callback verifying it.
+pub const GAS_FOR_HUB_POLICY_CALLBACK: Gas = 60_000_000_000_000;
+
+/// Gas for verifying the withdrawal from the stream.
+pub const GAS_FOR_STREAM_WITHDRAW_CALLBACK: Gas = 10_000_000_000_000;
+
+/// Gas for verifying the payment of recurring transfer.
+pub const GAS_FOR_RECURRING_TRANSFER_CALLBACK: Gas = 10_000_000_000_000;
+
+/// Gas for verifying the transfer made within spending allowance.
+pub const GAS_FOR_SPEND_CALLBACK: Gas = 10_000_000_000_000;
+
+/// Gas for the view call reading balance of the token held by this DAO.
+pub const GAS_FOR_TREASURY_VIEW: Gas = 10_000_000_000_000;
+
+/// Gas for recording balance of the token in the treasury.
+pub const GAS_FOR_SYNC_TREASURY_CALLBACK: Gas = 10_000_000_000_000;
+
+/// Gas for verifying the bounty payout.
+pub const GAS_FOR_BOUNTY_PAYOUT_CALLBACK: Gas = 10_000_000_000_000;
+
+/// Gas for the view call to the token contract checking holding for token gated role.
+pub const GAS_FOR_HOLDING_VIEW: Gas = 10_000_000_000_000;
+
+/// Gas for scheduling the holding check, rest of prepaid gas goes to the callback acting as the holder.
+pub const GAS_FOR_HOLDING_CHECK: Gas = 20_000_000_000_000;
+
+/// Gas for refunding the bond if the proposal wasn't added as holder.
+pub const GAS_FOR_HOLDER_PROPOSAL_REFUND: Gas = 10_000_000_000_000;
+
 /// Configuration of the DAO.
 #[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug)]
 #[serde(crate = "near_sdk::serde")]
@@ -59,15 +103,29 @@ pub enum Action {
     /// Vote to remove given proposal or bounty (because it's spam).
     VoteRemove,
     /// Finalize proposal, called when it's expired to return the funds
-    /// (or in the future can be used for early proposal closure).
+    /// or when it can't be approved anymore to close it early.
+    /// Approves delayed proposal after its grace period.
     Finalize,
     /// Move a proposal to the hub to shift into another DAO.
+    /// Fails, proposals are moved by the `MoveToHub` proposal.
     MoveToHub,
+    /// Retry execution of the proposal that failed.
+    Retry,
+    /// Replace previous vote on given proposal with the new one.
+    ChangeVote(Vote),
+    /// Withdraw previous vote on given proposal.
+    Unvote,
+    /// Vote in the poll for given options, by their index. Options are ranked for instant runoff.
+    VotePoll(Vec<u64>),
 }
 
 impl Action {
     pub fn to_policy_label(&self) -> String {
-        format!("{:?}", self)
+        match self {
+            Action::ChangeVote(_) => "ChangeVote".to_string(),
+            Action::VotePoll(_) => "VotePoll".to_string(),
+            _ => format!("{:?}", self),
+        }
     }
 }
 
@@ -1,4 +1,7 @@
 use std::cmp::min;
+use std::collections::HashMap;
+
+use near_sdk::json_types::{WrappedDuration, WrappedTimestamp};
 
 use crate::*;
 
@@ -10,6 +13,20 @@ pub struct ProposalOutput {
     pub id: u64,
     #[serde(flatten)]
     pub proposal: Proposal,
+    /// Winning option of the poll per role. Empty if it's not a poll.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub poll_winners: HashMap<String, u64>,
+}
+
+/// Member of the role whose term ends soon.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct ExpiringMember {
+    /// Name of the role.
+    pub role: String,
+    pub account_id: AccountId,
+    /// Time when the membership expires.
+    pub expires_at: WrappedTimestamp,
 }
 
 /// This is format of output via JSON for the bounty.
@@ -22,6 +39,41 @@ pub struct BountyOutput {
     pub bounty: Bounty,
 }
 
+/// This is format of output via JSON for the recurring transfer.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RecurringTransferOutput {
+    /// Id of the recurring transfer.
+    pub id: u64,
+    #[serde(flatten)]
+    pub recurring_transfer: RecurringTransfer,
+}
+
+/// Next payment of the recurring transfer.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct UpcomingPayment {
+    /// Id of the recurring transfer.
+    pub id: u64,
+    pub token_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    /// Time when the next unpaid period is due.
+    pub due_time: WrappedTimestamp,
+    /// Number of periods left to pay, including the next one.
+    pub remaining_periods: u32,
+}
+
+/// This is format of output via JSON for the stream.
+#[derive(Serialize, Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct StreamOutput {
+    /// Id of the stream.
+    pub id: u64,
+    #[serde(flatten)]
+    pub stream: Stream,
+}
+
 #[near_bindgen]
 impl Contract {
     /// Returns semver of this contract.
@@ -39,6 +91,36 @@ impl Contract {
         self.policy.get().unwrap().to_policy().clone()
     }
 
+    /// Returns members whose term in any role ends within given duration from now, sooner first.
+    pub fn get_expiring_members(&self, within: WrappedDuration) -> Vec<ExpiringMember> {
+        let now = env::block_timestamp();
+        let mut members: Vec<ExpiringMember> = self
+            .get_policy()
+            .roles
+            .into_iter()
+            .flat_map(|RolePermission { name, kind, .. }| match kind {
+                RoleKind::TermGroup(accounts) => accounts
+                    .into_iter()
+                    .filter_map(|(account_id, expires_at)| {
+                        let expires_at = expires_at?;
+                        if now < expires_at.0 && expires_at.0 <= now.saturating_add(within.0) {
+                            Some(ExpiringMember {
+                                role: name.clone(),
+                                account_id,
+                                expires_at,
+                            })
+                        } else {
+                            None
+                        }
+                    })
+                    .collect(),
+                _ => vec![],
+            })
+            .collect();
+        members.sort_by_key(|member| member.expires_at.0);
+        members
+    }
+
     /// Returns staking contract if available. Otherwise returns empty.
     pub fn get_staking_contract(&self) -> AccountId {
         self.staking_id.clone().unwrap_or_default()
@@ -49,9 +131,27 @@ impl Contract {
         env::storage_read(&CryptoHash::from(hash)).is_some()
     }
 
-    /// Returns available amount of NEAR that can be spent (outside of amount for storage and bonds).
+    /// Returns available amount of NEAR that can be spent (outside of amount for storage, bonds and
+    /// payouts of approved proposals).
     pub fn get_available_amount(&self) -> U128 {
-        U128(env::account_balance() - self.locked_amount)
+        U128(env::account_balance().saturating_sub(self.locked_amount))
+    }
+
+    /// Returns balances of fungible tokens deposited into the treasury, in paginated view.
+    pub fn get_treasury(&self, from_index: u64, limit: u64) -> Vec<(AccountId, TokenBalance)> {
+        (from_index..min(self.treasury_tokens.len(), from_index + limit))
+            .map(|index| {
+                let token_id = self.treasury_tokens.get(index).unwrap();
+                let balance = self.treasury.get(&token_id).unwrap();
+                (token_id, balance)
+            })
+            .collect()
+    }
+
+    /// Returns available amount of given token that can be spent (outside of amount reserved for approved proposals).
+    pub fn get_available_token_amount(&self, token_id: ValidAccountId) -> U128 {
+        let balance = self.internal_token_balance(token_id.as_ref());
+        U128(balance.amount.0.saturating_sub(balance.reserved.0))
     }
 
     /// Returns total delegated stake.
@@ -71,8 +171,8 @@ impl Contract {
     /// Returns delegated stake to given account.
     pub fn get_delegation_balances(&self, account_id: ValidAccountId) -> (Balance, Balance) {
         (
-            self.delegation_balance_of(account_id),
-            self.delegation_total_supply(account_id)
+            self.delegation_balance_of(account_id).0,
+            self.delegation_total_supply().0,
         )
     }
 
@@ -85,9 +185,10 @@ impl Contract {
     pub fn get_proposals(&self, from_index: u64, limit: u64) -> Vec<ProposalOutput> {
         (from_index..min(self.last_proposal_id, from_index + limit))
             .filter_map(|id| {
-                self.proposals.get(&id).map(|proposal| ProposalOutput {
+                self.internal_get_proposal(id).map(|proposal| ProposalOutput {
                     id,
-                    proposal: proposal.into(),
+                    poll_winners: proposal.poll_winners(),
+                    proposal,
                 })
             })
             .collect()
@@ -95,10 +196,11 @@ impl Contract {
 
     /// Get specific proposal.
     pub fn get_proposal(&self, id: u64) -> ProposalOutput {
-        let proposal = self.proposals.get(&id).expect("ERR_NO_PROPOSAL");
+        let proposal: Proposal = self.internal_get_proposal(id).expect("ERR_NO_PROPOSAL");
         ProposalOutput {
             id,
-            proposal: proposal.into(),
+            poll_winners: proposal.poll_winners(),
+            proposal,
         }
     }
 
@@ -139,4 +241,71 @@ impl Contract {
     pub fn get_bounty_number_of_claims(&self, id: u64) -> u32 {
         self.bounty_claims_count.get(&id).unwrap_or_default()
     }
+
+    /// Get given stream by id.
+    pub fn get_stream(&self, id: u64) -> StreamOutput {
+        let stream = self.streams.get(&id).expect("ERR_NO_STREAM");
+        StreamOutput {
+            id,
+            stream: stream.into(),
+        }
+    }
+
+    /// Get number of streams.
+    pub fn get_last_stream_id(&self) -> u64 {
+        self.last_stream_id
+    }
+
+    /// Get given recurring transfer by id.
+    pub fn get_recurring_transfer(&self, id: u64) -> RecurringTransferOutput {
+        let recurring_transfer = self
+            .recurring_transfers
+            .get(&id)
+            .expect("ERR_NO_RECURRING_TRANSFER");
+        RecurringTransferOutput {
+            id,
+            recurring_transfer: recurring_transfer.into(),
+        }
+    }
+
+    /// Get number of recurring transfers.
+    pub fn get_last_recurring_transfer_id(&self) -> u64 {
+        self.last_recurring_transfer_id
+    }
+
+    /// Get next payments of recurring transfers that are not fully paid, for `limit` of them from given index.
+    pub fn get_upcoming_payments(&self, from_index: u64, limit: u64) -> Vec<UpcomingPayment> {
+        (from_index..min(from_index + limit, self.last_recurring_transfer_id))
+            .filter_map(|id| {
+                let recurring_transfer: RecurringTransfer =
+                    self.recurring_transfers.get(&id)?.into();
+                if recurring_transfer.paid_periods >= recurring_transfer.times {
+                    return None;
+                }
+                Some(UpcomingPayment {
+                    id,
+                    due_time: WrappedTimestamp::from(
+                        recurring_transfer.start.0
+                            + recurring_transfer.period.0 * recurring_transfer.paid_periods as u64,
+                    ),
+                    remaining_periods: recurring_transfer.times - recurring_transfer.paid_periods,
+                    token_id: recurring_transfer.token_id,
+                    receiver_id: recurring_transfer.receiver_id,
+                    amount: recurring_transfer.amount,
+                })
+            })
+            .collect()
+    }
+
+    /// Get `limit` of streams from given index.
+    pub fn get_streams(&self, from_index: u64, limit: u64) -> Vec<StreamOutput> {
+        (from_index..min(from_index + limit, self.last_stream_id))
+            .filter_map(|id| {
+                self.streams.get(&id).map(|stream| StreamOutput {
+                    id,
+                    stream: stream.into(),
+                })
+            })
+            .collect()
+    }
 }
@@ -30,26 +30,40 @@ fn test_multi_council() {
                 name: "all".to_string(),
                 kind: RoleKind::Everyone,
                 permissions: vec!["*:AddProposal".to_string()].into_iter().collect(),
+                scoped_permissions: vec![],
                 vote_policy: HashMap::default(),
+                allowances: HashMap::default(),
+                parent: None,
             },
             RolePermission {
                 name: "council".to_string(),
                 kind: RoleKind::Group(vec![user(1), user(2)].into_iter().collect()),
                 permissions: vec!["*:*".to_string()].into_iter().collect(),
+                scoped_permissions: vec![],
                 vote_policy: HashMap::default(),
+                allowances: HashMap::default(),
+                parent: None,
             },
             RolePermission {
                 name: "community".to_string(),
                 kind: RoleKind::Group(vec![user(1), user(3), user(4)].into_iter().collect()),
                 permissions: vec!["*:*".to_string()].into_iter().collect(),
+                scoped_permissions: vec![],
                 vote_policy: HashMap::default(),
+                allowances: HashMap::default(),
+                parent: None,
             },
         ],
         default_vote_policy: VotePolicy::default(),
         proposal_bond: U128(10u128.pow(24)),
+        proposal_bond_token: base_token(),
         proposal_period: WrappedDuration::from(1_000_000_000 * 60 * 60 * 24 * 7),
+        proposal_bonds: HashMap::default(),
+        proposal_periods: HashMap::default(),
         bounty_bond: U128(10u128.pow(24)),
         bounty_forgiveness_period: WrappedDuration::from(1_000_000_000 * 60 * 60 * 24),
+        execution_delay: HashMap::default(),
+        hub_id: None,
     };
     add_proposal(
         &root,
@@ -221,6 +235,61 @@ fn test_create_dao_and_use_token() {
     );
 }
 
+/// Test moving proposal from one DAO into another.
+#[test]
+fn test_move_to_hub() {
+    let (root, dao) = setup_dao();
+    let hub = deploy_dao(
+        &root,
+        "hub".to_string(),
+        VersionedPolicy::Default(vec![root.account_id.clone()]),
+    );
+    let mut policy = view!(dao.get_policy()).unwrap_json::<Policy>();
+    policy.hub_id = Some(hub.account_id());
+    add_proposal(
+        &root,
+        &dao,
+        ProposalInput {
+            description: "set hub".to_string(),
+            kind: ProposalKind::ChangePolicy {
+                policy: VersionedPolicy::Current(policy),
+            },
+        },
+    )
+    .assert_success();
+    vote(vec![&root], &dao, 0);
+    add_proposal(
+        &root,
+        &dao,
+        ProposalInput {
+            description: "move me".to_string(),
+            kind: ProposalKind::Vote,
+        },
+    )
+    .assert_success();
+    // Moving action isn't allowed, proposal is moved by the approved `MoveToHub` proposal.
+    should_fail(call!(root, dao.act_proposal(1, Action::MoveToHub, None)));
+    add_proposal(
+        &root,
+        &dao,
+        ProposalInput {
+            description: "move to hub".to_string(),
+            kind: ProposalKind::MoveToHub { proposal_id: 1 },
+        },
+    )
+    .assert_success();
+    vote(vec![&root], &dao, 2);
+    let proposal = view!(dao.get_proposal(1)).unwrap_json::<Proposal>();
+    assert_eq!(proposal.status, ProposalStatus::Moved);
+    assert_eq!(proposal.moved_to, Some((hub.account_id(), 0)));
+    let moved = view!(hub.get_proposal(0)).unwrap_json::<Proposal>();
+    assert_eq!(moved.proposer, dao.account_id());
+    assert_eq!(moved.description, "move me");
+    assert_eq!(moved.status, ProposalStatus::InProgress);
+    // Moved proposal can't be voted on anymore.
+    should_fail(call!(root, dao.act_proposal(1, Action::VoteApprove, None)));
+}
+
 /// Test various cases that must fail.
 #[test]
 fn test_failures() {
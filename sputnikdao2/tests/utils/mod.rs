@@ -36,20 +36,28 @@ pub fn should_fail(r: ExecutionResult) {
 
 pub fn setup_dao() -> (UserAccount, Contract) {
     let root = init_simulator(None);
+    let dao = deploy_dao(
+        &root,
+        "dao".to_string(),
+        VersionedPolicy::Default(vec![root.account_id.clone()]),
+    );
+    (root, dao)
+}
+
+pub fn deploy_dao(root: &UserAccount, dao_id: AccountId, policy: VersionedPolicy) -> Contract {
     let config = Config {
         name: "test".to_string(),
         purpose: "to test".to_string(),
         metadata: Base64VecU8(vec![]),
     };
-    let dao = deploy!(
+    deploy!(
         contract: DAOContract,
-        contract_id: "dao".to_string(),
+        contract_id: dao_id,
         bytes: &DAO_WASM_BYTES,
         signer_account: root,
         deposit: to_yocto("200"),
-        init_method: new(config, VersionedPolicy::Default(vec![root.account_id.clone()]))
-    );
-    (root, dao)
+        init_method: new(config, policy)
+    )
 }
 
 pub fn setup_test_token(root: &UserAccount) -> ContractAccount<TestTokenContract> {
@@ -95,6 +103,7 @@ pub fn add_member_proposal(
             kind: ProposalKind::AddMemberToRole {
                 member_id: to_va(member_id),
                 role: "council".to_string(),
+                term: None,
             },
         },
     )